@@ -68,6 +68,38 @@ pub fn spawn_ascii_text(
         .id()
 }
 
+/// Breaks text into lines of at most `width` characters at spaces, keeping its own line breaks
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        // Counted in characters, the bytes of a non-ASCII one would throw the widths off
+        let mut line_width = 0;
+        for word in paragraph.split_whitespace() {
+            let word_width = word.chars().count();
+            if line_width > 0 && line_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += word_width;
+            // Words longer than a whole line get cut wherever the line ends
+            while line_width > width {
+                let (split, _) = line.char_indices().nth(width).unwrap();
+                let rest = line.split_off(split);
+                lines.push(std::mem::replace(&mut line, rest));
+                line_width -= width;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
 pub fn spawn_ascii_sprite(
     commands: &mut Commands,
     ascii: &AsciiSheet,
@@ -212,3 +244,14 @@ fn load_ascii(
 
     commands.insert_resource(AsciiSheet(atlas_handle));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_words_are_cut_by_characters() {
+        assert_eq!(wrap_text("a äöüäöü b", 4), vec!["a", "äöüä", "öü b"]);
+        assert_eq!(wrap_text("one two\nthree", 7), vec!["one two", "three"]);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::combat::{AttackEvent, CombatState};
 use crate::player::{Player, WalkedGroundType};
+use crate::settings::{step_volume, Settings};
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_kira_audio::{AudioApp, AudioChannel, AudioPlugin, AudioSource};
@@ -20,9 +21,6 @@ pub struct AudioState {
     hit_handle: Handle<AudioSource>,
     reward_handle: Handle<AudioSource>,
     death_handle: Handle<AudioSource>,
-    bgm_volume: f32,
-    combat_music_volume: f32,
-    is_muted: bool,
 }
 
 struct NormalFootsteps(Vec<Handle<AudioSource>>);
@@ -41,6 +39,7 @@ impl Plugin for GameAudioPlugin {
             .add_audio_channel::<SfxChannel>()
             .add_startup_system_to_stage(StartupStage::PreStartup, load_audio)
             .add_system(bgm_volume_control)
+            .add_system(apply_volume_settings.after(bgm_volume_control))
             .add_system(play_hit_sfx)
             .add_system_set(SystemSet::on_enter(GameState::Combat).with_system(start_combat_music))
             .add_system_set(SystemSet::on_enter(CombatState::Reward).with_system(play_reward_sfx))
@@ -76,6 +75,7 @@ fn play_footsteps_sfx(
 }
 
 //noinspection RsTypeCheck
+#[allow(clippy::ptr_arg)]
 fn pick_random_sound(sounds: &Vec<Handle<AudioSource>>) -> Handle<AudioSource> {
     let mut rng = thread_rng();
 
@@ -85,9 +85,10 @@ fn pick_random_sound(sounds: &Vec<Handle<AudioSource>>) -> Handle<AudioSource> {
 fn play_death_sfx(
     combat_music_channel: Res<AudioChannel<CombatMusicChannel>>,
     audio_state: Res<AudioState>,
+    settings: Res<Settings>,
 ) {
     combat_music_channel.stop();
-    combat_music_channel.set_volume(0.4);
+    combat_music_channel.set_volume(settings.volume(0.4));
     combat_music_channel.play(audio_state.death_handle.clone());
 }
 
@@ -105,45 +106,46 @@ fn play_hit_sfx(
     }
 }
 
-fn bgm_volume_control(
-    keyboard: Res<Input<KeyCode>>,
-    bgm_channel: Res<AudioChannel<BgmChannel>>,
-    combat_music_channel: Res<AudioChannel<CombatMusicChannel>>,
-    mut audio_state: ResMut<AudioState>,
-) {
-    let step = 0.05;
-
+/// Shortcuts for the music volume and mute that also work outside of the settings menu
+fn bgm_volume_control(keyboard: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
     if keyboard.just_pressed(KeyCode::Up) {
-        audio_state.bgm_volume += step;
-        audio_state.bgm_volume = audio_state.bgm_volume.clamp(0.0, 1.0);
-        bgm_channel.set_volume(audio_state.bgm_volume);
+        settings.bgm_volume = step_volume(settings.bgm_volume, 1);
     }
 
     if keyboard.just_pressed(KeyCode::Down) {
-        audio_state.bgm_volume -= step;
-        audio_state.bgm_volume = audio_state.bgm_volume.clamp(0.0, 1.0);
-        bgm_channel.set_volume(audio_state.bgm_volume);
+        settings.bgm_volume = step_volume(settings.bgm_volume, -1);
     }
 
     if keyboard.just_pressed(KeyCode::M) {
-        if audio_state.is_muted {
-            bgm_channel.set_volume(audio_state.bgm_volume);
-            combat_music_channel.set_volume(audio_state.combat_music_volume);
-        } else {
-            bgm_channel.set_volume(0.0);
-            combat_music_channel.set_volume(0.0);
-        }
-
-        audio_state.is_muted = !audio_state.is_muted;
+        settings.muted = !settings.muted;
     }
 }
 
+/// Also runs on the first frame, which is what gets the saved volumes onto the channels
+fn apply_volume_settings(
+    settings: Res<Settings>,
+    bgm_channel: Res<AudioChannel<BgmChannel>>,
+    combat_music_channel: Res<AudioChannel<CombatMusicChannel>>,
+    sfx_channel: Res<AudioChannel<SfxChannel>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    bgm_channel.set_volume(settings.volume(settings.bgm_volume));
+    combat_music_channel.set_volume(settings.volume(settings.combat_music_volume));
+    sfx_channel.set_volume(settings.volume(settings.sfx_volume));
+}
+
 fn start_combat_music(
     bgm_channel: Res<AudioChannel<BgmChannel>>,
     combat_music_channel: Res<AudioChannel<CombatMusicChannel>>,
     audio_state: Res<AudioState>,
+    settings: Res<Settings>,
 ) {
     bgm_channel.pause();
+    // The death jingle turns the channel up, a new fight shouldn't inherit that
+    combat_music_channel.set_volume(settings.volume(settings.combat_music_volume));
     combat_music_channel.play_looped(audio_state.combat_music_handle.clone());
 }
 
@@ -155,36 +157,19 @@ fn stop_combat_music(
     bgm_channel.resume();
 }
 
-fn load_audio(
-    mut commands: Commands,
-    assets: Res<AssetServer>,
-    bgm_channel: Res<AudioChannel<BgmChannel>>,
-    combat_music_channel: Res<AudioChannel<CombatMusicChannel>>,
-    sfx_channel: Res<AudioChannel<SfxChannel>>,
-) {
+fn load_audio(mut commands: Commands, assets: Res<AssetServer>) {
     let bgm_handle = assets.load("music/bip-bop.ogg");
     let combat_music_handle = assets.load("music/ganxta.ogg");
     let hit_handle = assets.load("sounds/hit.wav");
     let reward_handle = assets.load("sounds/reward.wav");
     let death_handle = assets.load("sounds/dead.wav");
 
-    let bgm_volume = 0.05;
-    let combat_music_volume = 0.2;
-    let sfx_volume = 0.1;
-
-    bgm_channel.set_volume(bgm_volume);
-    combat_music_channel.set_volume(combat_music_volume);
-    sfx_channel.set_volume(sfx_volume);
-
     commands.insert_resource(AudioState {
         bgm_handle,
         combat_music_handle,
         hit_handle,
         reward_handle,
         death_handle,
-        bgm_volume,
-        combat_music_volume,
-        is_muted: false,
     });
 
     let normal_footsteps: Vec<Handle<AudioSource>> = [
@@ -1,5 +1,6 @@
 use crate::combat::CombatState;
 use crate::player::{player_movement, Player};
+use crate::tilemap::MapSpawnedEvent;
 use crate::{GameState, RESOLUTION};
 use bevy::prelude::*;
 use bevy::render::camera::{Camera2d, ScalingMode};
@@ -28,13 +29,14 @@ impl Plugin for CameraPlugin {
             .add_system_set(SystemSet::on_enter(GameState::Overworld).with_system(spawn_camera))
             .add_system_set(
                 SystemSet::on_update(GameState::Overworld)
-                    .with_system(camera_movement.after(player_movement)),
+                    .with_system(camera_movement.after(player_movement))
+                    .with_system(snap_camera_to_player.before(camera_movement)),
             )
             .add_system_set(
-                SystemSet::on_pause(GameState::Overworld).with_system(save_and_reset_camera_scale),
+                SystemSet::on_enter(GameState::Combat).with_system(save_and_reset_camera_scale),
             )
             .add_system_set(
-                SystemSet::on_resume(GameState::Overworld).with_system(restore_camera_scale),
+                SystemSet::on_exit(GameState::Combat).with_system(restore_camera_scale),
             )
             .add_system_set(
                 SystemSet::on_update(CombatState::Dead).with_system(zoom_into_game_over_text),
@@ -74,6 +76,20 @@ fn camera_movement(
     }
 }
 
+fn snap_camera_to_player(
+    mut ev_map_spawned: EventReader<MapSpawnedEvent>,
+    player_query: Query<&Transform, With<Player>>,
+    mut camera_query: Query<&mut Transform, (Without<Player>, With<Camera2d>)>,
+) {
+    // Don't pan across the whole map after a warp
+    if ev_map_spawned.iter().count() > 0 {
+        let player_transform = player_query.single();
+        let mut camera_transform = camera_query.single_mut();
+        camera_transform.translation.x = player_transform.translation.x;
+        camera_transform.translation.y = player_transform.translation.y;
+    }
+}
+
 fn save_and_reset_camera_scale(
     mut camera_query: Query<&mut Transform, With<Camera2d>>,
     mut overworld_camera_data: ResMut<OverworldCameraData>,
@@ -129,6 +145,7 @@ fn zoom_into_game_over_text(
     }
 }
 
+#[allow(clippy::neg_multiply)]
 fn spawn_camera(mut commands: Commands, old_camera_query: Query<Entity, With<Camera2d>>) {
     // Despawn old cameras if they exist
     for ent in old_camera_query.iter() {
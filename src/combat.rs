@@ -1,28 +1,299 @@
-use crate::ascii::{spawn_ascii_text, spawn_nine_slice, AsciiSheet, NineSlice, NineSliceIndices};
+use crate::ascii::{
+    spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, AsciiSheet, NineSlice, NineSliceIndices,
+};
+use crate::data::RonAssetLoader;
+use crate::equipment::StatBonus;
 use crate::fadeout::create_fadeout;
 use crate::game_ui::{
-    create_health_bar, CreateTextPopupEvent, HealthBar, HealthBarBg, HealthBarType,
+    create_health_bar, CreateTextPopupEvent, HealthBarBg, HealthBarType, PopupPriority,
     TextPopupPosition,
 };
 use crate::graphics::{spawn_enemy_sprite, CharacterSheet};
+use crate::inventory::{item_name, Inventory, ItemAssets, ItemDatabase, Wallet};
+use crate::party::PartyMember;
 use crate::player::Player;
+use crate::status::{Infliction, StatusEffects, StatusTick};
 use crate::{GameState, RESOLUTION, TILE_SIZE};
+use anyhow::bail;
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
 use bevy_inspector_egui::Inspectable;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 pub struct CombatPlugin;
 
 #[derive(Component, Inspectable)]
 pub struct Enemy {
-    enemy_type: EnemyType,
+    id: String,
+    name: String,
+    level: usize,
+    exp: usize,
+}
+
+/// Enemy archetypes keyed by id, loaded from `assets/data/enemies.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "b0fdef57-401f-4b07-a58a-d60d105dc5f7"]
+pub struct EnemyDatabase {
+    enemies: HashMap<String, EnemyDefinition>,
+    /// Shared between enemy types, referenced by id from their definitions
+    #[serde(default)]
+    behaviours: HashMap<String, Behaviour>,
+}
+
+#[derive(Deserialize)]
+pub struct EnemyDefinition {
+    name: String,
+    /// Animation frames in the character sheet
+    frames: Vec<usize>,
+    stats: EnemyStats,
+    /// Added to the base stats for every level above 1
+    #[serde(default)]
+    growth: EnemyStats,
+    /// Exp given by a level 1 enemy, multiplied by the level
+    exp: usize,
+    #[serde(default)]
+    attack: AttackStyle,
+    /// Damage multipliers for elements the enemy is weak to or resists, 1.0 for any other
+    #[serde(default)]
+    affinities: HashMap<Element, f32>,
+    /// Gold dropped by a level 1 enemy, multiplied by the level
+    #[serde(default)]
+    gold: usize,
+    #[serde(default)]
+    drops: Vec<Drop>,
+    /// Id of the behaviour picking its actions, plain attacks every turn if there's none
+    #[serde(default)]
+    behaviour: Option<String>,
+}
+
+/// How an enemy picks what to do on its turn
+#[derive(Deserialize, Clone)]
+pub struct Behaviour {
+    actions: Vec<WeightedAction>,
+    /// Bosses get to act more than once per turn
+    #[serde(default = "Behaviour::single_action")]
+    actions_per_turn: usize,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WeightedAction {
+    action: EnemyAction,
+    weight: u32,
+    /// Only considered once the enemy's health drops under this fraction
+    #[serde(default)]
+    below_health: Option<f32>,
+    /// Shown when the action is announced, a generic line is used without one
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub enum EnemyAction {
+    /// The enemy's own attack
+    Attack,
+    /// Some other attack
+    Special(AttackStyle),
+    /// Restores this much of the enemy's health
+    Heal(isize),
+    /// Spends the turn winding up and lets the attack loose on the next one
+    Charge(AttackStyle),
+    /// Leaves the fight without giving any exp
+    Flee,
+}
+
+#[derive(Component)]
+pub struct EnemyBehaviour {
+    behaviour: Behaviour,
+}
+
+/// Attack an enemy is winding up, it goes off on the enemy's next turn
+#[derive(Component)]
+pub struct Charging {
+    style: AttackStyle,
+    name: String,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+pub struct EnemyStats {
+    health: isize,
+    attack: isize,
+    defense: isize,
+    speed: isize,
+    #[serde(default)]
+    luck: isize,
+    #[serde(default)]
+    evasion: isize,
+}
+
+/// How an enemy hits when it attacks
+#[derive(Component)]
+pub struct EnemyAttack {
+    style: AttackStyle,
+}
+
+/// Item an enemy has a chance to leave behind when defeated
+#[derive(Deserialize, Clone)]
+pub struct Drop {
+    item: String,
+    chance: f32,
+}
+
+/// Gold and drop table of a spawned enemy
+#[derive(Component)]
+pub struct Loot {
+    gold: usize,
+    drops: Vec<Drop>,
+}
+
+/// How much of each element's damage an enemy takes
+#[derive(Component)]
+pub struct Affinities(HashMap<Element, f32>);
+
+#[derive(Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Element {
+    Physical,
+    Fire,
+    Ice,
+    Light,
+    Dark,
 }
 
+pub struct EnemyAssets {
+    database: Handle<EnemyDatabase>,
+}
+
+/// Also read by the map loader to check encounter tables against
+pub const ENEMY_DATABASE_PATH: &str = "data/enemies.ron";
+
+pub const MAX_ENEMIES: usize = 4;
+
+/// Enemies that can show up in one map region, tables are defined per map
+#[derive(Deserialize, Clone)]
+pub struct EncounterTable {
+    /// Inclusive range of how many enemies attack at once
+    #[serde(default = "EncounterTable::single_enemy")]
+    group: (usize, usize),
+    /// Running away always fails, meant for bosses
+    #[serde(default)]
+    pub(crate) unescapable: bool,
+    enemies: Vec<EncounterEntry>,
+}
+
+/// One weighted line of an encounter table
+#[derive(Deserialize, Clone)]
+pub struct EncounterEntry {
+    enemy: String,
+    weight: u32,
+    /// Inclusive range of levels the enemy can show up at
+    levels: (usize, usize),
+}
+
+/// Encounter table of the region the player got ambushed in
+pub struct PendingEncounter {
+    pub(crate) table: EncounterTable,
+    /// Id of the boss tile the fight started from, `None` for random encounters
+    pub(crate) boss: Option<String>,
+    /// Set once an enemy runs off, a fight that ends like that isn't won
+    pub(crate) enemy_fled: bool,
+}
+
+/// Exp, gold and items handed out together, as a resource it collects the loot of the current fight
+#[derive(Default, Clone, Deserialize)]
+pub struct Rewards {
+    #[serde(default)]
+    pub(crate) exp: usize,
+    #[serde(default)]
+    pub(crate) gold: usize,
+    /// Item ids in the order they dropped
+    #[serde(default)]
+    pub(crate) items: Vec<String>,
+}
+
+/// Hands rewards to the party, the exp is shared by every member
+pub struct GiveRewardEvent {
+    pub(crate) rewards: Rewards,
+    /// Shown above what was earned
+    pub(crate) heading: Option<String>,
+}
+
+pub struct EnemyDefeatedEvent {
+    pub(crate) id: String,
+}
+
+/// Initiative order of the current round, rebuilt from speed whenever it runs out
+#[derive(Default)]
+pub struct TurnOrder {
+    /// Combatants that still have to act this round, fastest first
+    queue: Vec<Entity>,
+    current: Option<Entity>,
+    /// Actions the current enemy took this turn
+    actions_taken: usize,
+    /// Whether the current enemy acts again once its last action played out
+    follow_up: bool,
+}
+
+#[derive(Component)]
+pub struct TurnOrderText;
+
+/// Announces what the last enemy did
+#[derive(Component)]
+pub struct ActionText;
+
+/// Enemy picked in the target selection sub-menu
+#[derive(Default)]
+pub struct CombatTarget {
+    index: usize,
+}
+
+#[derive(Component)]
+pub struct TargetCursor;
+
+/// Negative damage heals, skipping defense
 pub struct AttackEvent {
-    target: Entity,
+    pub(crate) target: Entity,
     pub damage_amount: isize,
-    next_state: CombatState,
+    pub(crate) hits: usize,
+    /// Ignores the target's defense
+    pub(crate) pierce: bool,
+    pub(crate) inflicts: Option<Infliction>,
+    /// Chance for each hit to deal double damage, from the attacker's luck
+    pub(crate) crit_chance: f32,
+    pub(crate) element: Element,
+    pub(crate) next_state: CombatState,
+}
+
+/// How an attack hits, a plain one unless a skill says otherwise
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AttackStyle {
+    /// Multiplier for the attacker's attack stat
+    pub(crate) power: f32,
+    pub(crate) hits: usize,
+    pub(crate) pierce: bool,
+    pub(crate) inflicts: Option<Infliction>,
+    pub(crate) element: Element,
+}
+
+/// Attack the player goes for once a target is picked and the MP it costs
+pub struct PlayerAttackChoice {
+    pub(crate) style: AttackStyle,
+    pub(crate) mp_cost: isize,
 }
 
+/// Vertical menu opened from the combat menu, one line per entry id
+#[derive(Component)]
+pub struct CombatListMenu {
+    entries: Vec<String>,
+    selected: usize,
+}
+
+#[derive(Component)]
+pub struct ListCursor;
+
 pub struct ExpReceivedEvent {
     pub(crate) levelup_percentage: f32,
 }
@@ -31,22 +302,41 @@ pub struct LevelupEvent {
     pub(crate) new_level: usize,
 }
 
-#[derive(Component, Inspectable)]
+#[derive(Component, Inspectable, Serialize, Deserialize, Clone)]
 pub struct CombatStats {
     pub health: isize,
     pub max_health: isize,
     pub attack: isize,
     pub defense: isize,
+    pub speed: isize,
+    /// Saves from before skills existed have no MP
+    #[serde(default)]
+    pub mp: isize,
+    #[serde(default)]
+    pub max_mp: isize,
+    /// Percent chance to land a critical hit
+    #[serde(default)]
+    pub luck: isize,
+    /// Percent chance to dodge a hit
+    #[serde(default)]
+    pub evasion: isize,
 }
 
-pub const MENU_COUNT: isize = 2;
-
 #[derive(Component, PartialEq, Eq, Clone, Copy)]
 pub enum CombatMenuOption {
     Fight,
+    Skill,
+    Defend,
+    Item,
     Run,
 }
 
+pub const MENU_COUNT: isize = CombatMenuOption::ALL.len() as isize;
+
+/// Halves the damage taken until the guarding combatant's next turn
+#[derive(Component)]
+pub struct Guarding;
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub struct CombatMenuSelection {
     selected: CombatMenuOption,
@@ -54,10 +344,18 @@ pub struct CombatMenuSelection {
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum CombatState {
+    NextTurn,
     PlayerTurn,
+    PlayerTarget,
+    PlayerSkill,
+    PlayerItem,
     PlayerAttack,
     EnemyTurn(bool),
     EnemyAttack,
+    /// A party member's turn, they pick their own actions
+    AllyTurn(bool),
+    /// Left the fight with an item, nothing takes input until the fadeout is over
+    Escaping,
     Reward,
     Dead,
 }
@@ -65,47 +363,105 @@ pub enum CombatState {
 pub struct AttackEffects {
     timer: Timer,
     flash_speed: f32,
+    target: Option<Entity>,
 }
 
 #[derive(Component)]
 pub struct CombatText;
 
-#[derive(Clone, Copy, Inspectable)]
-pub enum EnemyType {
-    Bat,
-    Ghost,
+/// Source of every roll made in a fight, replace it with `CombatRng::seeded`
+/// to get the same outcomes every time
+pub struct CombatRng(StdRng);
+
+/// How a single hit of an attack landed
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HitRoll {
+    Miss,
+    Hit(isize),
+    Crit(isize),
+}
+
+/// Damage number drifting up from a combatant until its timer runs out
+#[derive(Component)]
+pub struct FloatingText {
+    timer: Timer,
 }
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
-        app.add_state(CombatState::PlayerTurn)
+        app.add_asset::<EnemyDatabase>()
+            .add_asset_loader(
+                RonAssetLoader::<EnemyDatabase>::new(&["enemies.ron"])
+                    .validated(EnemyDatabase::validate),
+            )
+            .add_startup_system(load_enemy_database)
+            .add_state(CombatState::PlayerTurn)
             .insert_resource(AttackEffects {
                 timer: Timer::from_seconds(0.4, true),
                 flash_speed: 0.1,
+                target: None,
+            })
+            .init_resource::<Rewards>()
+            .init_resource::<TurnOrder>()
+            .init_resource::<CombatTarget>()
+            .init_resource::<CombatRng>()
+            .insert_resource(PlayerAttackChoice {
+                style: AttackStyle::default(),
+                mp_cost: 0,
             })
             .add_event::<AttackEvent>()
             .add_event::<LevelupEvent>()
             .add_event::<ExpReceivedEvent>()
+            .add_event::<GiveRewardEvent>()
+            .add_event::<EnemyDefeatedEvent>()
+            .add_system(grant_rewards)
             .insert_resource(CombatMenuSelection {
                 selected: CombatMenuOption::Fight,
             })
+            .add_system_set(SystemSet::on_update(CombatState::NextTurn).with_system(next_turn))
             .add_system_set(
                 SystemSet::on_update(CombatState::EnemyTurn(false)).with_system(process_enemy_turn),
             )
             .add_system_set(
                 SystemSet::on_update(GameState::Combat)
+                    .with_system(show_guard)
+                    .with_system(update_floating_text)
                     .with_system(process_attack)
                     .with_system(combat_input)
                     .with_system(highlight_combat_buttons),
             )
+            .add_system_set(
+                SystemSet::on_enter(CombatState::PlayerTarget).with_system(spawn_target_cursor),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::PlayerTarget)
+                    .with_system(target_selection_input)
+                    .with_system(update_target_cursor.after(target_selection_input)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(CombatState::PlayerTarget).with_system(despawn_target_cursor),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::PlayerSkill).with_system(combat_list_input),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::PlayerItem).with_system(combat_list_input),
+            )
+            .add_system_set(
+                SystemSet::on_exit(CombatState::PlayerSkill).with_system(despawn_combat_list_menu),
+            )
+            .add_system_set(
+                SystemSet::on_exit(CombatState::PlayerItem).with_system(despawn_combat_list_menu),
+            )
             .add_system_set(
                 SystemSet::on_enter(GameState::Combat)
-                    .with_system(spawn_enemy)
+                    .with_system(spawn_enemies)
                     .with_system(start_combat)
                     .with_system(spawn_combat_menu),
             )
             .add_system_set(
                 SystemSet::on_exit(GameState::Combat)
+                    .with_system(stop_guarding)
                     .with_system(despawn_menu)
                     .with_system(despawn_all_combat_text)
                     .with_system(despawn_enemy),
@@ -142,38 +498,82 @@ fn show_game_over_screen(mut commands: Commands, ascii: Res<AsciiSheet>) {
 fn give_reward(
     mut commands: Commands,
     ascii: Res<AsciiSheet>,
-    mut player_query: Query<(&mut Player, &mut CombatStats)>,
-    enemy_query: Query<&Enemy>,
+    rewards: Res<Rewards>,
     mut keyboard: ResMut<Input<KeyCode>>,
+    mut ev_give_reward: EventWriter<GiveRewardEvent>,
+) {
+    keyboard.clear();
+
+    ev_give_reward.send(GiveRewardEvent {
+        rewards: rewards.clone(),
+        heading: None,
+    });
+
+    create_fadeout(&mut commands, None, &ascii);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn grant_rewards(
+    mut ev_give_reward: EventReader<GiveRewardEvent>,
+    mut player_query: Query<(&mut Player, &mut CombatStats, &mut Inventory, &mut Wallet)>,
+    mut member_query: Query<(&mut PartyMember, &mut CombatStats), Without<Player>>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
     mut ev_levelup: EventWriter<LevelupEvent>,
     mut ev_exp_received: EventWriter<ExpReceivedEvent>,
     mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
 ) {
-    keyboard.clear();
+    for GiveRewardEvent { rewards, heading } in ev_give_reward.iter() {
+        let exp_reward = rewards.exp;
+        let (mut player, mut stats, mut inventory, mut wallet) = player_query.single_mut();
 
-    let exp_reward = match enemy_query.single().enemy_type {
-        EnemyType::Bat => 10,
-        EnemyType::Ghost => 30,
-    };
-    let mut reward_text = format!("Earned {} exp", exp_reward);
-    let (mut player, mut stats) = player_query.single_mut();
-    if player.level_up(exp_reward, &mut stats) {
-        ev_levelup.send(LevelupEvent {
-            new_level: player.level,
-        });
-        reward_text += "\nLevel up!";
-    }
+        let mut earned = vec![format!("{} exp", exp_reward)];
+        if rewards.gold > 0 {
+            wallet.gold += rewards.gold;
+            earned.push(format!("{} gold", rewards.gold));
+        }
+        let database = item_databases.get(&item_assets.database);
+        let mut left_behind = Vec::new();
+        for item in rewards.items.iter() {
+            if inventory.add(item, 1) == 0 {
+                earned.push(item_name(database, item));
+            } else {
+                left_behind.push(item_name(database, item));
+            }
+        }
+        let mut reward_text = format!("Earned {}", earned.join(", "));
+        if !left_behind.is_empty() {
+            reward_text += &format!("\nNo room for {}", left_behind.join(", "));
+        }
+        if let Some(heading) = heading {
+            reward_text = format!("{}\n{}", heading, reward_text);
+        }
 
-    ev_text_popup.send(CreateTextPopupEvent {
-        text: reward_text,
-        position: TextPopupPosition::Left,
-        duration: 2.5,
-    });
+        if player.level_up(exp_reward, &mut stats) {
+            ev_levelup.send(LevelupEvent {
+                new_level: player.level,
+            });
+            reward_text += "\nLevel up!";
+        }
 
-    let levelup_percentage = player.exp as f32 / player.xp_required_for_current_level() as f32;
-    ev_exp_received.send(ExpReceivedEvent { levelup_percentage });
+        // The whole party shares the exp, knocked out or not
+        for (mut member, mut stats) in member_query.iter_mut() {
+            if member.level_up(exp_reward, &mut stats) {
+                reward_text += &format!("\n{} reached level {}!", member.name, member.level);
+            }
+        }
 
-    create_fadeout(&mut commands, None, &ascii);
+        ev_text_popup.send(CreateTextPopupEvent {
+            text: reward_text,
+            position: TextPopupPosition::Left,
+            duration: 2.5,
+            priority: PopupPriority::High,
+            ..default()
+        });
+
+        let levelup_percentage = player.exp as f32 / player.xp_required_for_current_level() as f32;
+        ev_exp_received.send(ExpReceivedEvent { levelup_percentage });
+    }
 }
 
 fn despawn_all_combat_text(mut commands: Commands, text_query: Query<Entity, With<CombatText>>) {
@@ -182,65 +582,759 @@ fn despawn_all_combat_text(mut commands: Commands, text_query: Query<Entity, Wit
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn handle_attack_effects(
     mut attack_fx: ResMut<AttackEffects>,
     time: Res<Time>,
+    turn_order: Res<TurnOrder>,
     mut enemy_graphics_query: Query<&mut Visibility, (With<Enemy>, Without<Player>)>,
-    mut player_graphics_query: Query<&mut Visibility, (With<Player>, Without<Enemy>)>,
+    mut player_graphics_query: Query<
+        &mut Visibility,
+        (Or<(With<Player>, With<PartyMember>)>, Without<Enemy>),
+    >,
     mut state: ResMut<State<CombatState>>,
 ) {
     attack_fx.timer.tick(time.delta());
-    let mut enemy_sprite = enemy_graphics_query.iter_mut().next().unwrap();
-    if state.current() == &CombatState::PlayerAttack {
-        if attack_fx.timer.elapsed_secs() % attack_fx.flash_speed > attack_fx.flash_speed / 2.0 {
-            enemy_sprite.is_visible = false;
-        } else {
-            enemy_sprite.is_visible = true;
+    let flash_visible =
+        attack_fx.timer.elapsed_secs() % attack_fx.flash_speed <= attack_fx.flash_speed / 2.0;
+
+    // The target may already be despawned if the hit defeated it
+    if let Some(target) = attack_fx.target {
+        if let Ok(mut enemy_sprite) = enemy_graphics_query.get_mut(target) {
+            enemy_sprite.is_visible = flash_visible;
+        } else if let Ok(mut party_sprite) = player_graphics_query.get_mut(target) {
+            party_sprite.is_visible = flash_visible;
         }
     }
 
-    let mut player_sprite = player_graphics_query.iter_mut().next().unwrap();
-    if state.current() == &CombatState::EnemyAttack {
-        if attack_fx.timer.elapsed_secs() % attack_fx.flash_speed > attack_fx.flash_speed / 2.0 {
-            player_sprite.is_visible = false;
-        } else {
+    if attack_fx.timer.just_finished() {
+        for mut enemy_sprite in enemy_graphics_query.iter_mut() {
+            enemy_sprite.is_visible = true;
+        }
+        for mut player_sprite in player_graphics_query.iter_mut() {
             player_sprite.is_visible = true;
         }
-    }
 
-    if attack_fx.timer.just_finished() {
-        enemy_sprite.is_visible = true;
-        player_sprite.is_visible = true;
-        if state.current() == &CombatState::PlayerAttack {
-            state.set(CombatState::EnemyTurn(false)).unwrap()
+        if state.current() == &CombatState::EnemyAttack && turn_order.follow_up {
+            state.set(CombatState::EnemyTurn(false)).unwrap();
         } else {
-            state.set(CombatState::PlayerTurn).unwrap()
+            state.set(CombatState::NextTurn).unwrap();
         }
     }
 }
 
-fn start_combat(mut combat_state: ResMut<State<CombatState>>) {
-    //TODO speed and turn calculations
-    let _ = combat_state.set(CombatState::PlayerTurn);
+fn start_combat(
+    mut combat_state: ResMut<State<CombatState>>,
+    mut rewards: ResMut<Rewards>,
+    mut turn_order: ResMut<TurnOrder>,
+    mut target: ResMut<CombatTarget>,
+) {
+    *rewards = Rewards::default();
+    *turn_order = TurnOrder::default();
+    target.index = 0;
+
+    let _ = combat_state.set(CombatState::NextTurn);
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn next_turn(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    mut turn_order: ResMut<TurnOrder>,
+    mut combatant_query: Query<(
+        Entity,
+        &mut CombatStats,
+        Option<&Enemy>,
+        Option<&StatBonus>,
+        Option<&mut StatusEffects>,
+        Option<&PartyMember>,
+    )>,
+    text_query: Query<Entity, With<TurnOrderText>>,
+    mut combat_state: ResMut<State<CombatState>>,
+    mut rng: ResMut<CombatRng>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    // Skip whoever got defeated since the round started
+    turn_order.queue.retain(|&entity| {
+        combatant_query
+            .get(entity)
+            .is_ok_and(|(_, stats, _, _, _, _)| stats.health > 0)
+    });
+
+    // Statuses tick at the start of a turn, stunned combatants lose it to the next one in line
+    let mut status_text = Vec::new();
+    let current = loop {
+        if turn_order.queue.is_empty() {
+            let mut combatants: Vec<(Entity, isize)> = combatant_query
+                .iter()
+                .filter(|(_, stats, _, _, _, _)| stats.health > 0)
+                .map(|(entity, stats, _, bonus, _, _)| (entity, stats.derived(bonus).speed))
+                .collect();
+
+            // Shuffled first so that combatants with equal speed don't always act in the same order
+            rng.shuffle(&mut combatants);
+            combatants.sort_by_key(|&(_, speed)| std::cmp::Reverse(speed));
+            turn_order.queue = combatants.into_iter().map(|(entity, _)| entity).collect();
+        }
+
+        let candidate = turn_order.queue.remove(0);
+        commands.entity(candidate).remove::<Guarding>();
+        let (_, mut stats, enemy, _, statuses, member) =
+            combatant_query.get_mut(candidate).unwrap();
+        let tick = match statuses {
+            Some(mut statuses) => statuses.tick(),
+            None => StatusTick::default(),
+        };
+        let name = combatant_name(enemy, member);
+
+        if tick.poison_damage > 0 {
+            // Poison never takes the last hit point, finishing someone off is up to attacks
+            let damage = tick.poison_damage.min(stats.health - 1);
+            if damage > 0 {
+                stats.health -= damage;
+                status_text.push(format!("{} took {} poison damage.", name, damage));
+            }
+        }
+
+        if !tick.stunned {
+            break candidate;
+        }
+        status_text.push(format!("{} can't move!", name));
+    };
+    turn_order.current = Some(current);
+    turn_order.actions_taken = 0;
+    turn_order.follow_up = false;
+
+    if !status_text.is_empty() {
+        ev_text_popup.send(CreateTextPopupEvent {
+            text: status_text.join("\n"),
+            position: TextPopupPosition::Center,
+            duration: 2.0,
+            ..default()
+        });
+    }
+
+    for text in text_query.iter() {
+        commands.entity(text).despawn_recursive();
+    }
+
+    let names: Vec<&str> = std::iter::once(current)
+        .chain(turn_order.queue.iter().copied())
+        .map(|entity| match combatant_query.get(entity) {
+            Ok((_, _, enemy, _, _, member)) => combatant_name(enemy, member),
+            Err(_) => "You",
+        })
+        .collect();
+    let text = format!("Turn: {}", names.join(">"));
+    let turn_order_text = spawn_ascii_text(
+        &mut commands,
+        &ascii,
+        &text,
+        Vec3::new(-RESOLUTION + TILE_SIZE, 1.0 - TILE_SIZE, 100.0),
+    );
+    commands
+        .entity(turn_order_text)
+        .insert(TurnOrderText)
+        .insert(CombatText);
+
+    match combatant_query.get(current).unwrap() {
+        (_, _, Some(_), _, _, _) => combat_state.set(CombatState::EnemyTurn(false)).unwrap(),
+        (_, _, _, _, _, Some(_)) => combat_state.set(CombatState::AllyTurn(false)).unwrap(),
+        _ => combat_state.set(CombatState::PlayerTurn).unwrap(),
+    }
+}
+
+fn combatant_name<'a>(enemy: Option<&'a Enemy>, member: Option<&'a PartyMember>) -> &'a str {
+    match (enemy, member) {
+        (Some(enemy), _) => enemy.name.as_str(),
+        (_, Some(member)) => member.name.as_str(),
+        _ => "You",
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn process_enemy_turn(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
     mut attack_event: EventWriter<AttackEvent>,
     mut combat_state: ResMut<State<CombatState>>,
-    enemy_query: Query<&CombatStats, With<Enemy>>,
-    player_query: Query<Entity, With<Player>>,
+    mut turn_order: ResMut<TurnOrder>,
+    mut rng: ResMut<CombatRng>,
+    mut attack_fx: ResMut<AttackEffects>,
+    mut encounter: ResMut<PendingEncounter>,
+    enemy_query: Query<(
+        Entity,
+        &Enemy,
+        &CombatStats,
+        Option<&StatBonus>,
+        &EnemyAttack,
+        &EnemyBehaviour,
+        Option<&Charging>,
+    )>,
+    party_query: Query<(Entity, &CombatStats), Or<(With<Player>, With<PartyMember>)>>,
+    text_query: Query<Entity, With<ActionText>>,
 ) {
-    let player_ent = player_query.single();
-    //todo support multiple enemies
-    let enemy_stats = enemy_query.iter().next().unwrap();
-
-    attack_event.send(AttackEvent {
-        target: player_ent,
-        damage_amount: enemy_stats.attack,
-        next_state: CombatState::EnemyAttack,
-    });
+    // Enemies go after anyone in the party still standing
+    let living: Vec<Entity> = party_query
+        .iter()
+        .filter(|(_, stats)| stats.health > 0)
+        .map(|(entity, _)| entity)
+        .collect();
+    let target = *rng
+        .pick(&living, |_| 1)
+        .expect("Enemy turn without anyone to attack!");
+    let (entity, enemy, enemy_stats, bonus, enemy_attack, behaviour, charging) = turn_order
+        .current
+        .and_then(|enemy| enemy_query.get(enemy).ok())
+        .expect("Enemy turn without an enemy!");
+    let behaviour = &behaviour.behaviour;
+    let stats = enemy_stats.derived(bonus);
+
+    turn_order.actions_taken += 1;
+    turn_order.follow_up = turn_order.actions_taken < behaviour.actions_per_turn;
+
+    // Waits on the attack event unless the action has nothing to hit
+    let mut next_state = CombatState::EnemyTurn(true);
+    let announcement = if let Some(charging) = charging {
+        commands.entity(entity).remove::<Charging>();
+        attack_event.send(stats.attack_event(&charging.style, target));
+        format!("{} unleashes {}!", enemy.name, charging.name)
+    } else {
+        let health_fraction = stats.health as f32 / stats.max_health as f32;
+        let (action, name) = match behaviour.pick(health_fraction, &mut rng) {
+            Some(weighted) => (weighted.action.clone(), weighted.name.as_ref()),
+            None => (EnemyAction::Attack, None),
+        };
+        let uses = name.map(|name| format!("{} uses {}!", enemy.name, name));
+
+        match action {
+            EnemyAction::Attack => {
+                attack_event.send(stats.attack_event(&enemy_attack.style, target));
+                uses.unwrap_or_else(|| format!("{} attacks!", enemy.name))
+            }
+            EnemyAction::Special(style) => {
+                attack_event.send(stats.attack_event(&style, target));
+                uses.unwrap_or_else(|| format!("{} attacks!", enemy.name))
+            }
+            EnemyAction::Heal(amount) => {
+                attack_event.send(AttackEvent {
+                    target: entity,
+                    damage_amount: -amount,
+                    hits: 1,
+                    pierce: true,
+                    inflicts: None,
+                    crit_chance: 0.0,
+                    element: Element::Physical,
+                    next_state: CombatState::EnemyAttack,
+                });
+                uses.unwrap_or_else(|| format!("{} recovers!", enemy.name))
+            }
+            EnemyAction::Charge(style) => {
+                let name = name.cloned().unwrap_or_else(|| "its attack".to_string());
+                // Winding up takes the rest of the turn, the attack only goes off on the next one
+                turn_order.follow_up = false;
+                // Flashing the enemy makes the wind up easy to spot
+                attack_fx.target = Some(entity);
+                next_state = CombatState::EnemyAttack;
+                let text = format!("{} is charging {}!", enemy.name, name);
+                commands.entity(entity).insert(Charging { style, name });
+                text
+            }
+            EnemyAction::Flee => {
+                commands.entity(entity).despawn_recursive();
+                turn_order.follow_up = false;
+                encounter.enemy_fled = true;
+
+                let enemies_left = enemy_query
+                    .iter()
+                    .any(|(other, _, stats, _, _, _, _)| other != entity && stats.health > 0);
+                if enemies_left {
+                    combat_state.set(CombatState::NextTurn).unwrap();
+                } else {
+                    combat_state.set(CombatState::Reward).unwrap();
+                }
+                spawn_action_text(
+                    &mut commands,
+                    &ascii,
+                    &text_query,
+                    &format!("{} flees!", enemy.name),
+                );
+                return;
+            }
+        }
+    };
+
+    spawn_action_text(&mut commands, &ascii, &text_query, &announcement);
+    combat_state.set(next_state).unwrap();
+}
+
+pub(crate) fn spawn_action_text(
+    commands: &mut Commands,
+    ascii: &AsciiSheet,
+    text_query: &Query<Entity, With<ActionText>>,
+    text: &str,
+) {
+    for old_text in text_query.iter() {
+        commands.entity(old_text).despawn_recursive();
+    }
+
+    let action_text = spawn_ascii_text(
+        commands,
+        ascii,
+        text,
+        Vec3::new(-RESOLUTION + TILE_SIZE, 1.0 - 2.0 * TILE_SIZE, 100.0),
+    );
+    commands
+        .entity(action_text)
+        .insert(ActionText)
+        .insert(CombatText);
+}
+
+/// Living enemies from left to right, the order they're picked in
+fn enemies_by_position(enemy_query: &Query<(Entity, &Transform), With<Enemy>>) -> Vec<Entity> {
+    let mut enemies: Vec<(Entity, f32)> = enemy_query
+        .iter()
+        .map(|(entity, transform)| (entity, transform.translation.x))
+        .collect();
+    enemies.sort_by(|a, b| a.1.total_cmp(&b.1));
+    enemies.into_iter().map(|(entity, _)| entity).collect()
+}
+
+fn target_selection_input(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut fight_event: EventWriter<AttackEvent>,
+    mut player_query: Query<(&mut CombatStats, Option<&StatBonus>), With<Player>>,
+    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+    mut target: ResMut<CombatTarget>,
+    choice: Res<PlayerAttackChoice>,
+    mut combat_state: ResMut<State<CombatState>>,
+) {
+    let enemies = enemies_by_position(&enemy_query);
+    if enemies.is_empty() {
+        return;
+    }
+
+    let enemy_count = enemies.len() as isize;
+    let mut new_index = (target.index as isize).min(enemy_count - 1);
+    if keyboard.just_pressed(KeyCode::A) {
+        new_index -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::D) {
+        new_index += 1;
+    }
+    target.index = ((new_index + enemy_count) % enemy_count) as usize;
+
+    if keyboard.just_pressed(KeyCode::E) {
+        let (mut player_stats, bonus) = player_query.single_mut();
+        // Skills are only paid for once there's no backing out of them
+        player_stats.mp -= choice.mp_cost;
+        let attack = player_stats.derived(bonus).attack as f32 * choice.style.power;
+        fight_event.send(AttackEvent {
+            target: enemies[target.index],
+            damage_amount: attack.round() as isize,
+            hits: choice.style.hits,
+            pierce: choice.style.pierce,
+            inflicts: choice.style.inflicts.clone(),
+            crit_chance: player_stats.crit_chance(),
+            element: choice.style.element,
+            next_state: CombatState::PlayerAttack,
+        });
+    } else if keyboard.clear_just_pressed(KeyCode::Escape) {
+        combat_state.set(CombatState::PlayerTurn).unwrap();
+    }
+}
+
+fn spawn_target_cursor(mut commands: Commands, ascii: Res<AsciiSheet>) {
+    // ▼ in the ascii sheet, placed by update_target_cursor
+    let cursor = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        31,
+        Color::RED,
+        Vec3::new(0.0, 0.0, 150.0),
+        Vec3::splat(1.5),
+    );
+    commands
+        .entity(cursor)
+        .insert(TargetCursor)
+        .insert(Name::new("TargetCursor"));
+}
+
+fn update_target_cursor(
+    target: Res<CombatTarget>,
+    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+    mut cursor_query: Query<&mut Transform, (With<TargetCursor>, Without<Enemy>)>,
+) {
+    let enemies = enemies_by_position(&enemy_query);
+    if let (Some(&enemy), Ok(mut cursor_transform)) =
+        (enemies.get(target.index), cursor_query.get_single_mut())
+    {
+        let (_, enemy_transform) = enemy_query.get(enemy).unwrap();
+        cursor_transform.translation.x = enemy_transform.translation.x;
+        cursor_transform.translation.y = enemy_transform.translation.y + 0.5;
+    }
+}
+
+fn despawn_target_cursor(mut commands: Commands, cursor_query: Query<Entity, With<TargetCursor>>) {
+    for cursor in cursor_query.iter() {
+        commands.entity(cursor).despawn_recursive();
+    }
+}
+
+impl CombatMenuOption {
+    /// Every option, in the order they're shown from left to right
+    pub const ALL: [CombatMenuOption; 5] = [
+        CombatMenuOption::Fight,
+        CombatMenuOption::Skill,
+        CombatMenuOption::Defend,
+        CombatMenuOption::Item,
+        CombatMenuOption::Run,
+    ];
+
+    fn index(&self) -> usize {
+        CombatMenuOption::ALL
+            .iter()
+            .position(|option| option == self)
+            .unwrap()
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            CombatMenuOption::Fight => "Fight",
+            CombatMenuOption::Skill => "Skill",
+            CombatMenuOption::Defend => "Defend",
+            CombatMenuOption::Item => "Item",
+            CombatMenuOption::Run => "Run",
+        }
+    }
+}
+
+impl Default for CombatRng {
+    fn default() -> Self {
+        CombatRng(StdRng::from_entropy())
+    }
+}
+
+impl CombatRng {
+    pub fn seeded(seed: u64) -> Self {
+        CombatRng(StdRng::seed_from_u64(seed))
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T], weight: impl Fn(&T) -> u32) -> Option<&'a T> {
+        items.choose_weighted(&mut self.0, weight).ok()
+    }
 
-    combat_state.set(CombatState::EnemyTurn(true)).unwrap();
+    pub fn chance(&mut self, chance: f32) -> bool {
+        self.0.gen::<f32>() < chance
+    }
+
+    pub fn range(&mut self, min: usize, max: usize) -> usize {
+        self.0.gen_range(min..=max)
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        items.shuffle(&mut self.0);
+    }
+
+    /// Rolls one hit of `damage` against the target's defense, give or take a fifth
+    pub fn roll_hit(
+        &mut self,
+        damage: isize,
+        defense: isize,
+        crit_chance: f32,
+        evade_chance: f32,
+    ) -> HitRoll {
+        if self.chance(evade_chance) {
+            return HitRoll::Miss;
+        }
+
+        let spread = self.0.gen_range(0.8..=1.2);
+        // Lowest damage possible is 0 so we don't heal the target instead
+        let damage = ((damage - defense).max(0) as f32 * spread).round() as isize;
+        if self.chance(crit_chance) {
+            HitRoll::Crit((damage * 2).max(1))
+        } else {
+            HitRoll::Hit(damage)
+        }
+    }
+}
+
+impl HitRoll {
+    fn damage(&self) -> isize {
+        match self {
+            HitRoll::Miss => 0,
+            HitRoll::Hit(damage) | HitRoll::Crit(damage) => *damage,
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            HitRoll::Miss => "MISS".to_string(),
+            HitRoll::Hit(damage) => damage.to_string(),
+            HitRoll::Crit(damage) => format!("CRIT! {}", damage),
+        }
+    }
+}
+
+impl TurnOrder {
+    pub fn current(&self) -> Option<Entity> {
+        self.current
+    }
+}
+
+impl Behaviour {
+    fn single_action() -> usize {
+        1
+    }
+
+    /// Weighted pick among the actions allowed at this much health
+    fn pick(&self, health_fraction: f32, rng: &mut CombatRng) -> Option<&WeightedAction> {
+        let allowed: Vec<&WeightedAction> = self
+            .actions
+            .iter()
+            .filter(|action| {
+                action
+                    .below_health
+                    .is_none_or(|below| health_fraction < below)
+            })
+            .collect();
+        rng.pick(&allowed, |action| action.weight).copied()
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Behaviour {
+            actions: vec![WeightedAction {
+                action: EnemyAction::Attack,
+                weight: 1,
+                below_health: None,
+                name: None,
+            }],
+            actions_per_turn: 1,
+        }
+    }
+}
+
+impl Affinities {
+    fn multiplier(&self, element: Element) -> f32 {
+        self.0.get(&element).copied().unwrap_or(1.0)
+    }
+}
+
+impl CombatStats {
+    pub fn crit_chance(&self) -> f32 {
+        self.luck.clamp(0, 100) as f32 / 100.0
+    }
+
+    /// Event for an attack in this style from a combatant with these stats
+    pub(crate) fn attack_event(&self, style: &AttackStyle, target: Entity) -> AttackEvent {
+        let attack = self.attack as f32 * style.power;
+        AttackEvent {
+            target,
+            damage_amount: attack.round() as isize,
+            hits: style.hits,
+            pierce: style.pierce,
+            inflicts: style.inflicts.clone(),
+            crit_chance: self.crit_chance(),
+            element: style.element,
+            next_state: CombatState::EnemyAttack,
+        }
+    }
+
+    /// Capped so nothing becomes impossible to hit
+    pub fn evade_chance(&self) -> f32 {
+        self.evasion.clamp(0, 75) as f32 / 100.0
+    }
+}
+
+impl Default for AttackStyle {
+    fn default() -> Self {
+        AttackStyle {
+            power: 1.0,
+            hits: 1,
+            pierce: false,
+            inflicts: None,
+            element: Element::Physical,
+        }
+    }
+}
+
+impl CombatListMenu {
+    pub fn selected(&self) -> Option<&String> {
+        self.entries.get(self.selected)
+    }
+}
+
+/// Boxed list in the lower left corner with a cursor, entries are (id, line) pairs.
+/// The header gets a box of its own on top that the cursor never goes to
+pub fn spawn_combat_list_menu(
+    commands: &mut Commands,
+    ascii: &AsciiSheet,
+    indices: &NineSliceIndices,
+    name: &str,
+    header: Option<String>,
+    entries: Vec<(String, String)>,
+) -> Entity {
+    // Room for the cursor in front of the lines and the borders around them
+    let width = (entries
+        .iter()
+        .map(|(_, line)| line.len())
+        .chain(header.iter().map(String::len))
+        .max()
+        .unwrap_or(0)
+        + 4) as f32;
+    let height = (entries.len() + 2) as f32;
+    let center = Vec3::new(
+        -RESOLUTION + width * TILE_SIZE / 2.0,
+        -1.0 + height * TILE_SIZE / 2.0 + 0.04,
+        110.0,
+    );
+
+    let nine_slice = spawn_nine_slice(commands, ascii, indices, width, height);
+    let mut children = vec![nine_slice];
+    for (i, (_, line)) in entries.iter().enumerate() {
+        children.push(spawn_ascii_text(
+            commands,
+            ascii,
+            line,
+            Vec3::new(
+                (-width / 2.0 + 2.5) * TILE_SIZE,
+                list_line_y(height, i),
+                1.0,
+            ),
+        ));
+    }
+
+    // ► in the ascii sheet, moved by combat_list_input
+    let cursor = spawn_ascii_sprite(
+        commands,
+        ascii,
+        16,
+        Color::RED,
+        Vec3::new(
+            (-width / 2.0 + 1.5) * TILE_SIZE,
+            list_line_y(height, 0),
+            1.0,
+        ),
+        Vec3::splat(1.0),
+    );
+    commands.entity(cursor).insert(ListCursor);
+    children.push(cursor);
+
+    if let Some(header) = header {
+        let header_height = 3.0;
+        let header_box = spawn_nine_slice(commands, ascii, indices, width, header_height);
+        let header_text = spawn_ascii_text(
+            commands,
+            ascii,
+            &header,
+            Vec3::new((-width / 2.0 + 2.5) * TILE_SIZE, 0.0, 1.0),
+        );
+        let header = commands
+            .spawn()
+            .insert(Transform::from_translation(Vec3::new(
+                0.0,
+                (height + header_height) / 2.0 * TILE_SIZE,
+                0.0,
+            )))
+            .insert(GlobalTransform::default())
+            .insert(Name::new("Header"))
+            .push_children(&[header_box, header_text])
+            .id();
+        children.push(header);
+    }
+
+    commands
+        .spawn()
+        .insert(Transform::from_translation(center))
+        .insert(GlobalTransform::default())
+        .insert(Name::new(name.to_string()))
+        .insert(CombatListMenu {
+            entries: entries.into_iter().map(|(id, _)| id).collect(),
+            selected: 0,
+        })
+        .push_children(&children)
+        .id()
+}
+
+/// Lines are laid out top down starting right under the top border
+fn list_line_y(height: f32, line: usize) -> f32 {
+    (height / 2.0 - 1.5 - line as f32) * TILE_SIZE
+}
+
+fn combat_list_input(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut menu_query: Query<(&mut CombatListMenu, &Children)>,
+    mut cursor_query: Query<&mut Transform, With<ListCursor>>,
+    mut combat_state: ResMut<State<CombatState>>,
+) {
+    if keyboard.clear_just_pressed(KeyCode::Escape) {
+        combat_state.set(CombatState::PlayerTurn).unwrap();
+        return;
+    }
+
+    // The menu is spawned through commands, so it only shows up a frame after entering the state
+    let (mut menu, children) = match menu_query.get_single_mut() {
+        Ok((menu, children)) if !menu.entries.is_empty() => (menu, children),
+        _ => return,
+    };
+
+    let count = menu.entries.len() as isize;
+    let mut new_index = menu.selected as isize;
+    if keyboard.just_pressed(KeyCode::W) {
+        new_index -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::S) {
+        new_index += 1;
+    }
+    let new_index = ((new_index + count) % count) as usize;
+    if new_index != menu.selected {
+        menu.selected = new_index;
+    }
+
+    let height = (menu.entries.len() + 2) as f32;
+    for child in children.iter() {
+        if let Ok(mut transform) = cursor_query.get_mut(*child) {
+            transform.translation.y = list_line_y(height, menu.selected);
+        }
+    }
+}
+
+fn despawn_combat_list_menu(
+    mut commands: Commands,
+    menu_query: Query<Entity, With<CombatListMenu>>,
+) {
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+/// Tints the player blue while they're guarding
+fn show_guard(mut player_query: Query<(&mut TextureAtlasSprite, Option<&Guarding>), With<Player>>) {
+    for (mut sprite, guarding) in player_query.iter_mut() {
+        let color = if guarding.is_some() {
+            Color::rgb(0.5, 0.7, 1.0)
+        } else {
+            Color::WHITE
+        };
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+fn stop_guarding(
+    mut commands: Commands,
+    mut guard_query: Query<(Entity, Option<&mut TextureAtlasSprite>), With<Guarding>>,
+) {
+    for (entity, sprite) in guard_query.iter_mut() {
+        commands.entity(entity).remove::<Guarding>();
+        // show_guard doesn't run outside of combat to take the tint off
+        if let Some(mut sprite) = sprite {
+            sprite.color = Color::WHITE;
+        }
+    }
 }
 
 fn despawn_menu(mut commands: Commands, button_query: Query<Entity, With<CombatMenuOption>>) {
@@ -308,48 +1402,124 @@ fn spawn_combat_menu(
     let box_height = 3.0;
     let box_center_y = -1.0 + box_height * TILE_SIZE / 2.0 + 0.04;
 
-    let run_text = "Run";
-    let run_width = (run_text.len() + 2) as f32;
-    let run_center_x = RESOLUTION - (run_width * TILE_SIZE) / 2.0;
-
-    spawn_combat_button(
-        &mut commands,
-        &ascii,
-        &nine_slice_indices,
-        Vec3::new(run_center_x, box_center_y, 100.0),
-        run_text,
-        CombatMenuOption::Run,
-        Vec2::new(run_width, box_height),
-    );
+    // Laid out right to left so the last option sits in the corner
+    let mut right_edge = RESOLUTION;
+    for option in CombatMenuOption::ALL.iter().rev() {
+        let text = option.label();
+        let width = (text.len() + 2) as f32;
+        let center_x = right_edge - (width * TILE_SIZE) / 2.0;
 
-    let fight_text = "Fight";
-    let fight_width = (fight_text.len() + 2) as f32;
-    let fight_center_x = RESOLUTION - (run_width * TILE_SIZE) - (fight_width * TILE_SIZE / 2.0);
+        spawn_combat_button(
+            &mut commands,
+            &ascii,
+            &nine_slice_indices,
+            Vec3::new(center_x, box_center_y, 100.0),
+            text,
+            *option,
+            Vec2::new(width, box_height),
+        );
 
-    spawn_combat_button(
-        &mut commands,
-        &ascii,
-        &nine_slice_indices,
-        Vec3::new(fight_center_x, box_center_y, 100.0),
-        fight_text,
-        CombatMenuOption::Fight,
-        Vec2::new(fight_width, box_height),
-    );
+        right_edge -= width * TILE_SIZE;
+    }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn process_attack(
+    mut commands: Commands,
     mut attack_event: EventReader<AttackEvent>,
-    mut target_query: Query<(Entity, &mut CombatStats, Option<&mut Player>)>,
-    mut health_bar_query: Query<(&mut Transform, &HealthBar)>,
+    mut target_query: Query<(
+        Entity,
+        &mut CombatStats,
+        Option<&mut Player>,
+        Option<&Enemy>,
+        Option<&StatBonus>,
+        Option<&mut StatusEffects>,
+        Option<&Guarding>,
+        Option<&Affinities>,
+        Option<&PartyMember>,
+        &Transform,
+    )>,
+    ascii: Res<AsciiSheet>,
+    mut rng: ResMut<CombatRng>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+    mut attack_fx: ResMut<AttackEffects>,
+    loot_query: Query<&Loot>,
+    mut ev_enemy_defeated: EventWriter<EnemyDefeatedEvent>,
+    mut rewards: ResMut<Rewards>,
     mut combat_state: ResMut<State<CombatState>>,
 ) {
     for event in attack_event.iter() {
-        let (entity, mut target_stats, player_option) = target_query
+        let (
+            entity,
+            mut target_stats,
+            player_option,
+            enemy_option,
+            bonus,
+            statuses,
+            guarding,
+            affinities,
+            member,
+            transform,
+        ) = target_query
             .get_mut(event.target)
             .expect("Fight target without stats!");
+        attack_fx.target = Some(entity);
 
-        // Lowest damage possible is 0 so we don't heal the target instead
-        let resulting_damage = std::cmp::max(event.damage_amount - target_stats.defense, 0);
+        let target = target_stats.derived(bonus);
+        let defense = if event.pierce { 0 } else { target.defense };
+        let mut floating_texts = Vec::new();
+        // Whether anything got through, statuses only stick when it did
+        let mut landed = true;
+        let resulting_damage = if event.damage_amount < 0 {
+            // Healing always lands in full
+            floating_texts.push(format!("+{}", -event.damage_amount));
+            event.damage_amount * event.hits as isize
+        } else if event.damage_amount == 0 {
+            // Nothing to roll for attacks that only inflict a status, buffs included
+            0
+        } else {
+            let multiplier =
+                affinities.map_or(1.0, |affinities| affinities.multiplier(event.element));
+            landed = false;
+            let mut total = 0;
+            for _ in 0..event.hits {
+                let hit = rng.roll_hit(
+                    event.damage_amount,
+                    defense,
+                    event.crit_chance,
+                    target.evade_chance(),
+                );
+                landed |= hit != HitRoll::Miss;
+                let mut damage = (hit.damage() as f32 * multiplier).round() as isize;
+                if guarding.is_some() {
+                    damage /= 2;
+                }
+                total += damage;
+                floating_texts.push(match hit {
+                    HitRoll::Miss => hit.text(),
+                    HitRoll::Hit(_) => HitRoll::Hit(damage).text(),
+                    HitRoll::Crit(_) => HitRoll::Crit(damage).text(),
+                });
+            }
+
+            if landed && multiplier > 1.0 {
+                floating_texts.push("Weak!".to_string());
+            } else if landed && multiplier < 1.0 {
+                floating_texts.push("Resisted".to_string());
+            }
+            total
+        };
+
+        // Stacked upwards from just above the target's head
+        for (i, text) in floating_texts.iter().enumerate() {
+            let position = transform.translation
+                + Vec3::new(
+                    -(text.len() as f32) * TILE_SIZE / 2.0,
+                    0.25 + i as f32 * TILE_SIZE,
+                    200.0,
+                );
+            spawn_floating_text(&mut commands, &ascii, text, position);
+        }
 
         let mut target_is_player = false;
         if let Some(mut player) = player_option {
@@ -361,26 +1531,92 @@ fn process_attack(
                 trauma = dmg_relative_to_hp / big_hit_threshold;
             }
 
-            player.trauma += trauma;
+            if resulting_damage > 0 {
+                player.trauma += trauma;
+            }
         }
 
-        target_stats.health = std::cmp::max(target_stats.health - resulting_damage, 0);
+        target_stats.health =
+            (target_stats.health - resulting_damage).clamp(0, target_stats.max_health);
 
-        for (mut transform, health_bar) in health_bar_query.iter_mut() {
-            if entity == health_bar.entity {
-                let health_percent = target_stats.health as f32 / target_stats.max_health as f32;
-                transform.scale = Vec3::new(health_percent, 1.0, 1.0);
+        if let (Some(infliction), Some(mut statuses)) = (&event.inflicts, statuses) {
+            if landed && target_stats.health > 0 && rng.chance(infliction.chance) {
+                statuses.add(infliction.status.clone());
+                let name = combatant_name(enemy_option, member);
+                let verb = if target_is_player { "are" } else { "is" };
+                ev_text_popup.send(CreateTextPopupEvent {
+                    text: format!("{} {} {}!", name, verb, infliction.status.kind.name()),
+                    position: TextPopupPosition::Center,
+                    duration: 2.0,
+                    ..default()
+                });
             }
         }
 
-        if target_stats.health == 0 {
-            if target_is_player {
+        if target_stats.health > 0 {
+            combat_state.set(event.next_state).unwrap();
+        } else if target_is_player || member.is_some() {
+            // Knocked out members sit the rest of the fight out, it's over once nobody is left
+            let party_left =
+                target_query
+                    .iter()
+                    .any(|(_, stats, player, _, _, _, _, _, member, _)| {
+                        (player.is_some() || member.is_some()) && stats.health > 0
+                    });
+            if party_left {
+                combat_state.set(event.next_state).unwrap();
+            } else {
                 combat_state.set(CombatState::Dead).unwrap();
+            }
+        } else {
+            if let Some(enemy) = enemy_option {
+                rewards.exp += enemy.exp;
+                ev_enemy_defeated.send(EnemyDefeatedEvent {
+                    id: enemy.id.clone(),
+                });
+            }
+            if let Ok(loot) = loot_query.get(entity) {
+                rewards.gold += loot.gold;
+                for drop in loot.drops.iter() {
+                    if rng.chance(drop.chance) {
+                        rewards.items.push(drop.item.clone());
+                    }
+                }
+            }
+
+            let enemies_left = target_query
+                .iter()
+                .any(|(_, stats, _, enemy, _, _, _, _, _, _)| enemy.is_some() && stats.health > 0);
+            if enemies_left {
+                commands.entity(entity).despawn_recursive();
+                combat_state.set(event.next_state).unwrap();
             } else {
                 combat_state.set(CombatState::Reward).unwrap();
             }
-        } else {
-            combat_state.set(event.next_state).unwrap();
+        }
+    }
+}
+
+fn spawn_floating_text(commands: &mut Commands, ascii: &AsciiSheet, text: &str, position: Vec3) {
+    let floating_text = spawn_ascii_text(commands, ascii, text, position);
+    commands
+        .entity(floating_text)
+        .insert(FloatingText {
+            timer: Timer::from_seconds(0.8, false),
+        })
+        .insert(CombatText);
+}
+
+fn update_floating_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut text_query: Query<(Entity, &mut FloatingText, &mut Transform)>,
+) {
+    for (entity, mut floating_text, mut transform) in text_query.iter_mut() {
+        floating_text.timer.tick(time.delta());
+        transform.translation.y += 0.15 * time.delta_seconds();
+        if floating_text.timer.finished() {
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
@@ -429,21 +1665,33 @@ fn test_give_player_trauma(mut player_query: Query<&mut Player>, keyboard: Res<I
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn combat_input(
     mut commands: Commands,
     ascii: Res<AsciiSheet>,
-    keyboard: Res<Input<KeyCode>>,
-    mut fight_event: EventWriter<AttackEvent>,
-    player_query: Query<&CombatStats, With<Player>>,
-    enemy_query: Query<Entity, With<Enemy>>,
+    mut keyboard: ResMut<Input<KeyCode>>,
     mut menu_state: ResMut<CombatMenuSelection>,
-    combat_state: ResMut<State<CombatState>>,
+    mut combat_state: ResMut<State<CombatState>>,
+    player_query: Query<(
+        Entity,
+        &Player,
+        &CombatStats,
+        Option<&StatBonus>,
+        &Inventory,
+    )>,
+    enemy_query: Query<(&Enemy, &CombatStats, Option<&StatBonus>)>,
+    encounter: Res<PendingEncounter>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+    mut attack_choice: ResMut<PlayerAttackChoice>,
+    mut rng: ResMut<CombatRng>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
 ) {
     if combat_state.current() != &CombatState::PlayerTurn {
         return;
     }
 
-    let mut new_selection = menu_state.selected as isize;
+    let mut new_selection = menu_state.selected.index() as isize;
 
     if keyboard.just_pressed(KeyCode::A) {
         new_selection -= 1;
@@ -453,66 +1701,260 @@ fn combat_input(
     }
 
     new_selection = (new_selection + MENU_COUNT) % MENU_COUNT;
+    menu_state.selected = CombatMenuOption::ALL[new_selection as usize];
 
-    menu_state.selected = match new_selection {
-        0 => CombatMenuOption::Fight,
-        1 => CombatMenuOption::Run,
-        _ => CombatMenuOption::Run,
-    };
-
-    if keyboard.just_pressed(KeyCode::E) {
+    // Cleared so the sub-menu doesn't see the same press after the state change
+    if keyboard.clear_just_pressed(KeyCode::E) {
+        let player_entity = player_query.single().0;
         match menu_state.selected {
             CombatMenuOption::Fight => {
-                let player_stats = player_query.single();
-                //todo handle multiple enemies and enemy selection
-                let target = enemy_query.iter().next().unwrap();
-                fight_event.send(AttackEvent {
-                    target,
-                    damage_amount: player_stats.attack,
-                    next_state: CombatState::PlayerAttack,
-                })
+                *attack_choice = PlayerAttackChoice {
+                    style: AttackStyle::default(),
+                    mp_cost: 0,
+                };
+                combat_state.set(CombatState::PlayerTarget).unwrap();
+            }
+            CombatMenuOption::Skill => combat_state.set(CombatState::PlayerSkill).unwrap(),
+            CombatMenuOption::Defend => {
+                commands.entity(player_entity).insert(Guarding);
+                ev_text_popup.send(CreateTextPopupEvent {
+                    text: "You brace yourself.".to_string(),
+                    position: TextPopupPosition::Center,
+                    duration: 1.5,
+                    ..default()
+                });
+                combat_state.set(CombatState::NextTurn).unwrap();
+            }
+            CombatMenuOption::Item => {
+                let has_items = item_databases
+                    .get(&item_assets.database)
+                    .is_some_and(|database| {
+                        !database.combat_items(player_query.single().4).is_empty()
+                    });
+                if !has_items {
+                    ev_text_popup.send(CreateTextPopupEvent {
+                        text: "You have no items!".to_string(),
+                        position: TextPopupPosition::Center,
+                        duration: 2.0,
+                        ..default()
+                    });
+                } else {
+                    combat_state.set(CombatState::PlayerItem).unwrap();
+                }
+            }
+            CombatMenuOption::Run => {
+                let (_, player, stats, bonus, _) = player_query.single();
+                let enemies = enemy_query
+                    .iter()
+                    .map(|(enemy, stats, bonus)| (enemy.level, stats.derived(bonus).speed));
+                let chance = escape_chance(player.level, stats.derived(bonus).speed, enemies);
+
+                if encounter.table.unescapable {
+                    ev_text_popup.send(CreateTextPopupEvent {
+                        text: "There's no running from this fight!".to_string(),
+                        position: TextPopupPosition::Center,
+                        duration: 2.0,
+                        ..default()
+                    });
+                } else if rng.chance(chance) {
+                    create_fadeout(&mut commands, None, &ascii);
+                } else {
+                    // A failed attempt still uses up the turn
+                    ev_text_popup.send(CreateTextPopupEvent {
+                        text: "Couldn't escape!".to_string(),
+                        position: TextPopupPosition::Center,
+                        duration: 2.0,
+                        ..default()
+                    });
+                    combat_state.set(CombatState::NextTurn).unwrap();
+                }
             }
-            CombatMenuOption::Run => create_fadeout(&mut commands, None, &ascii),
         }
     }
 }
 
-fn spawn_enemy(mut commands: Commands, characters: Res<CharacterSheet>) {
-    let enemy_type = match rand::random::<f32>() {
-        x if x < 0.5 => EnemyType::Bat,
-        _ => EnemyType::Ghost,
-    };
+/// Even odds against an equal group, better for every point of speed or level over the
+/// fastest and strongest enemy, never quite certain either way
+fn escape_chance(
+    player_level: usize,
+    player_speed: isize,
+    enemies: impl Iterator<Item = (usize, isize)>,
+) -> f32 {
+    let (max_level, max_speed) = enemies.fold((0, 0), |(max_level, max_speed), (level, speed)| {
+        (max_level.max(level), max_speed.max(speed))
+    });
 
-    let stats = match enemy_type {
-        EnemyType::Bat => CombatStats {
-            health: 3,
-            max_health: 3,
-            attack: 2,
-            defense: 1,
-        },
-        EnemyType::Ghost => CombatStats {
-            health: 5,
-            max_health: 5,
-            attack: 3,
-            defense: 2,
-        },
-    };
+    let speed_edge = (player_speed - max_speed) as f32 * 0.1;
+    let level_edge = (player_level as f32 - max_level as f32) * 0.05;
+    (0.5 + speed_edge + level_edge).clamp(0.1, 0.95)
+}
 
-    let sprite = spawn_enemy_sprite(
-        &mut commands,
-        &characters,
-        Vec3::new(0.0, 0.5, 100.0),
-        enemy_type,
-    );
+fn load_enemy_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(EnemyAssets {
+        database: assets.load(ENEMY_DATABASE_PATH),
+    });
+}
 
-    let health_bar_bg = create_health_bar(&mut commands, HealthBarType::Enemy, sprite);
-    commands.entity(sprite).add_child(health_bar_bg);
+impl EnemyDatabase {
+    pub fn contains(&self, enemy: &str) -> bool {
+        self.enemies.contains_key(enemy)
+    }
 
-    commands
-        .entity(sprite)
-        .insert(Enemy { enemy_type })
-        .insert(stats)
-        .insert(Name::new("Enemy"));
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        for (id, enemy) in self.enemies.iter() {
+            if enemy.frames.is_empty() {
+                bail!("enemy '{}' has no animation frames", id);
+            }
+            if let Some(behaviour) = &enemy.behaviour {
+                if !self.behaviours.contains_key(behaviour) {
+                    bail!("enemy '{}' uses unknown behaviour '{}'", id, behaviour);
+                }
+            }
+        }
+
+        for (id, behaviour) in self.behaviours.iter() {
+            if behaviour.actions_per_turn == 0 {
+                bail!("behaviour '{}' never gets to act", id);
+            }
+            if behaviour.actions.iter().all(|action| action.weight == 0) {
+                bail!("behaviour '{}' has no action with a weight above 0", id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl EnemyDefinition {
+    fn stats_at_level(&self, level: usize) -> CombatStats {
+        let levels_gained = level.saturating_sub(1) as isize;
+        let health = self.stats.health + self.growth.health * levels_gained;
+        CombatStats {
+            health,
+            max_health: health,
+            attack: self.stats.attack + self.growth.attack * levels_gained,
+            defense: self.stats.defense + self.growth.defense * levels_gained,
+            speed: self.stats.speed + self.growth.speed * levels_gained,
+            mp: 0,
+            max_mp: 0,
+            luck: self.stats.luck + self.growth.luck * levels_gained,
+            evasion: self.stats.evasion + self.growth.evasion * levels_gained,
+        }
+    }
+}
+
+impl EncounterTable {
+    fn single_enemy() -> (usize, usize) {
+        (1, 1)
+    }
+
+    pub fn validate(&self, enemies: &EnemyDatabase) -> Result<(), anyhow::Error> {
+        if self.enemies.is_empty() {
+            bail!("encounter table has no enemies");
+        }
+
+        for entry in self.enemies.iter() {
+            if !enemies.contains(&entry.enemy) {
+                bail!("unknown enemy '{}'", entry.enemy);
+            }
+            if entry.weight == 0 {
+                bail!("enemy '{}' has a weight of 0", entry.enemy);
+            }
+            let (min, max) = entry.levels;
+            if min == 0 || min > max {
+                bail!(
+                    "levels ({}, {}) of enemy '{}' must start at 1 and go up",
+                    min,
+                    max,
+                    entry.enemy
+                );
+            }
+        }
+
+        let (min, max) = self.group;
+        if min == 0 || min > max || max > MAX_ENEMIES {
+            bail!(
+                "group size ({}, {}) must be within 1 to {} enemies",
+                min,
+                max,
+                MAX_ENEMIES
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn spawn_enemies(
+    mut commands: Commands,
+    characters: Res<CharacterSheet>,
+    encounter: Res<PendingEncounter>,
+    enemy_assets: Res<EnemyAssets>,
+    enemy_databases: Res<Assets<EnemyDatabase>>,
+    mut rng: ResMut<CombatRng>,
+) {
+    let database = enemy_databases
+        .get(&enemy_assets.database)
+        .expect("Enemy database is not loaded!");
+
+    let table = &encounter.table;
+    let enemy_count = rng.range(table.group.0, table.group.1);
+    let spacing = 0.8;
+
+    for i in 0..enemy_count {
+        let entry = rng
+            .pick(&table.enemies, |entry| entry.weight)
+            .expect("Encounter table is empty!");
+        // Maps are checked against the enemy database when they load, so this is only a safety net
+        let definition = match database.enemies.get(&entry.enemy) {
+            Some(definition) => definition,
+            None => {
+                error!("Unknown enemy '{}' in encounter table", entry.enemy);
+                continue;
+            }
+        };
+        let level = rng.range(entry.levels.0, entry.levels.1);
+        let behaviour = match &definition.behaviour {
+            Some(id) => database.behaviours.get(id).cloned().unwrap_or_else(|| {
+                panic!("Unknown behaviour '{}' for enemy '{}'!", id, entry.enemy)
+            }),
+            None => Behaviour::default(),
+        };
+
+        // Spread the enemies evenly around the center of the screen
+        let x = (i as f32 - (enemy_count - 1) as f32 / 2.0) * spacing;
+        let sprite = spawn_enemy_sprite(
+            &mut commands,
+            &characters,
+            Vec3::new(x, 0.5, 100.0),
+            &definition.frames,
+        );
+
+        let health_bar_bg = create_health_bar(&mut commands, HealthBarType::Enemy, sprite);
+        commands.entity(sprite).add_child(health_bar_bg);
+
+        commands
+            .entity(sprite)
+            .insert(Enemy {
+                id: entry.enemy.clone(),
+                name: definition.name.clone(),
+                level,
+                exp: definition.exp * level,
+            })
+            .insert(Loot {
+                gold: definition.gold * level,
+                drops: definition.drops.clone(),
+            })
+            .insert(definition.stats_at_level(level))
+            .insert(EnemyAttack {
+                style: definition.attack.clone(),
+            })
+            .insert(Affinities(definition.affinities.clone()))
+            .insert(EnemyBehaviour { behaviour })
+            .insert(StatBonus::default())
+            .insert(StatusEffects::default())
+            .insert(Name::new(format!("{} Lv{}", definition.name, level)));
+    }
 }
 
 fn despawn_enemy(mut commands: Commands, enemy_query: Query<Entity, With<Enemy>>) {
@@ -521,9 +1963,16 @@ fn despawn_enemy(mut commands: Commands, enemy_query: Query<Entity, With<Enemy>>
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn hide_player(
-    mut player_query: Query<(&mut Visibility, &Children), (With<Player>, Without<HealthBarBg>)>,
-    mut health_bar_bg_query: Query<&mut Visibility, (With<HealthBarBg>, Without<Player>)>,
+    mut player_query: Query<
+        (&mut Visibility, &Children),
+        (Or<(With<Player>, With<PartyMember>)>, Without<HealthBarBg>),
+    >,
+    mut health_bar_bg_query: Query<
+        &mut Visibility,
+        (With<HealthBarBg>, Without<Player>, Without<PartyMember>),
+    >,
 ) {
     for (mut visibility, children) in player_query.iter_mut() {
         visibility.is_visible = false;
@@ -534,3 +1983,91 @@ fn hide_player(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_rolls_repeat() {
+        let mut first = CombatRng::seeded(7);
+        let mut second = CombatRng::seeded(7);
+        for _ in 0..50 {
+            assert_eq!(
+                first.roll_hit(20, 5, 0.2, 0.2),
+                second.roll_hit(20, 5, 0.2, 0.2)
+            );
+        }
+    }
+
+    #[test]
+    fn certain_evade_always_misses() {
+        let mut rng = CombatRng::seeded(1);
+        for _ in 0..50 {
+            assert_eq!(rng.roll_hit(20, 5, 1.0, 1.0), HitRoll::Miss);
+        }
+    }
+
+    #[test]
+    fn damage_spread_stays_within_a_fifth() {
+        let mut rng = CombatRng::seeded(2);
+        for _ in 0..200 {
+            match rng.roll_hit(110, 10, 0.0, 0.0) {
+                HitRoll::Hit(damage) => assert!((80..=120).contains(&damage), "{}", damage),
+                roll => panic!("expected a plain hit, got {:?}", roll),
+            }
+        }
+    }
+
+    #[test]
+    fn crits_double_the_damage() {
+        let mut rng = CombatRng::seeded(3);
+        for _ in 0..200 {
+            match rng.roll_hit(110, 10, 1.0, 0.0) {
+                HitRoll::Crit(damage) => assert!((160..=240).contains(&damage), "{}", damage),
+                roll => panic!("expected a crit, got {:?}", roll),
+            }
+        }
+    }
+
+    #[test]
+    fn defense_never_heals_but_crits_still_hurt() {
+        let mut rng = CombatRng::seeded(4);
+        assert_eq!(rng.roll_hit(5, 50, 0.0, 0.0), HitRoll::Hit(0));
+        assert_eq!(rng.roll_hit(5, 50, 1.0, 0.0), HitRoll::Crit(1));
+    }
+
+    #[test]
+    fn escape_chance_is_even_against_an_equal_group() {
+        let chance = escape_chance(5, 10, [(5, 10), (3, 4)].into_iter());
+        assert!((chance - 0.5).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn escape_chance_is_never_certain() {
+        assert_eq!(escape_chance(50, 100, [(1, 1)].into_iter()), 0.95);
+        assert_eq!(escape_chance(1, 1, [(50, 100)].into_iter()), 0.1);
+    }
+
+    #[test]
+    fn shipped_enemies_are_valid() {
+        let database: EnemyDatabase =
+            ron::from_str(include_str!("../assets/data/enemies.ron")).unwrap();
+        database.validate().unwrap();
+    }
+
+    #[test]
+    fn enemy_without_frames_is_rejected() {
+        let database: EnemyDatabase = ron::from_str(
+            "(enemies: { \"blob\": (name: \"Blob\", frames: [], stats: (health: 1, attack: 1, defense: 0, speed: 1), exp: 1) })",
+        )
+        .unwrap();
+        assert!(database.validate().is_err());
+    }
+
+    #[test]
+    fn speed_and_level_edges_add_up() {
+        let chance = escape_chance(7, 12, [(5, 10)].into_iter());
+        assert!((chance - 0.8).abs() < 1e-5, "{}", chance);
+    }
+}
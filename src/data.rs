@@ -0,0 +1,44 @@
+use anyhow::Context;
+use bevy::asset::{Asset, AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use serde::de::DeserializeOwned;
+
+/// Loads a whole RON file as a single asset of type `T`
+pub struct RonAssetLoader<T> {
+    extensions: &'static [&'static str],
+    /// Catches mistakes the types can't, so they fail the load instead of a fight later on
+    validate: fn(&T) -> Result<(), anyhow::Error>,
+}
+
+impl<T> RonAssetLoader<T> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        RonAssetLoader {
+            extensions,
+            validate: |_| Ok(()),
+        }
+    }
+
+    pub fn validated(mut self, validate: fn(&T) -> Result<(), anyhow::Error>) -> Self {
+        self.validate = validate;
+        self
+    }
+}
+
+impl<T: Asset + DeserializeOwned> AssetLoader for RonAssetLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset: T = ron::de::from_bytes(bytes)?;
+            (self.validate)(&asset)
+                .with_context(|| format!("invalid {}", load_context.path().display()))?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
@@ -1,4 +1,4 @@
-use crate::combat::{CombatStats, Enemy};
+use crate::combat::{CombatRng, CombatStats, Enemy};
 use crate::game_ui::ExpBar;
 use crate::graphics::{FrameAnimation, PlayerGraphics};
 use crate::player::{EncounterTracker, Player};
@@ -18,6 +18,14 @@ impl Plugin for DebugPlugin {
                 .register_inspectable::<PlayerGraphics>()
                 .register_inspectable::<Enemy>()
                 .register_inspectable::<Player>();
+
+            // Replays the same rolls in every fight, handy for chasing down a bug
+            if let Some(seed) = std::env::var("LUDYRPG_SEED")
+                .ok()
+                .and_then(|seed| seed.parse().ok())
+            {
+                app.insert_resource(CombatRng::seeded(seed));
+            }
         }
     }
 }
@@ -0,0 +1,850 @@
+use crate::ascii::{
+    spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, wrap_text, AsciiSheet, NineSliceIndices,
+};
+use crate::combat::{CombatStats, PendingEncounter};
+use crate::data::RonAssetLoader;
+use crate::fadeout::create_fadeout;
+use crate::game_ui::{CreateTextPopupEvent, TextPopupPosition};
+use crate::inventory::Inventory;
+use crate::party::PartyMember;
+use crate::player::Player;
+use crate::quest::QuestLog;
+use crate::save::{LoadedGame, SaveGameEvent};
+use crate::status::StatusEffects;
+use crate::tilemap::{CurrentMap, MapDefinition};
+use crate::{GameState, TILE_SIZE};
+use anyhow::bail;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::Camera2d;
+use bevy::transform::TransformSystem;
+use bevy::utils::{HashMap, HashSet};
+use serde::Deserialize;
+
+pub struct DialoguePlugin;
+
+/// Nodes a dialogue may pass through without showing anything before it's assumed to loop
+const MAX_SILENT_NODES: usize = 32;
+/// Width of the dialogue box in tiles, the text gets the inside minus a margin on each side
+const DIALOGUE_BOX_WIDTH: usize = 32;
+pub const DIALOGUE_DATABASE_PATH: &str = "data/dialogue.ron";
+/// Characters revealed per second
+const DIALOGUE_TEXT_SPEED: f32 = 40.0;
+/// Seconds the "more" arrow takes to blink on and off
+const MORE_BLINK_PERIOD: f32 = 0.8;
+
+/// Conversations keyed by id, loaded from `assets/data/dialogue.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "3f9c6a17-8e2d-4b50-a4c1-7d05e9b2f836"]
+pub struct DialogueDatabase {
+    dialogues: HashMap<String, Dialogue>,
+}
+
+#[derive(Deserialize)]
+pub struct Dialogue {
+    /// Node the conversation opens with
+    start: String,
+    nodes: HashMap<String, DialogueNode>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueNode {
+    /// Run as soon as the node is reached, before its first page shows
+    #[serde(default)]
+    actions: Vec<DialogueAction>,
+    /// Shown one after another, each one advanced with the interact key
+    #[serde(default)]
+    pages: Vec<String>,
+    /// Offered with the last page, the ones whose conditions fail are left out
+    #[serde(default)]
+    choices: Vec<DialogueChoice>,
+    /// Without choices the first branch whose conditions hold is followed, none ends the dialogue
+    #[serde(default)]
+    next: Vec<DialogueBranch>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueChoice {
+    text: String,
+    #[serde(default)]
+    conditions: Vec<DialogueCondition>,
+    /// Node to go to, `None` ends the dialogue
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueBranch {
+    #[serde(default)]
+    conditions: Vec<DialogueCondition>,
+    node: String,
+}
+
+#[derive(Deserialize)]
+pub enum DialogueCondition {
+    /// The player is at least this level
+    Level(usize),
+    /// The player carries at least one of the item
+    HasItem(String),
+    Flag(String),
+    /// The story variable is at least this much
+    Variable(String, isize),
+    QuestActive(String),
+    QuestDone(String),
+    /// Someone in the party is missing health or MP, or suffers from a status
+    Hurt,
+    Not(Box<DialogueCondition>),
+}
+
+#[derive(Deserialize)]
+pub enum DialogueAction {
+    /// Restores the whole party
+    Heal,
+    GiveItem(String, usize),
+    SetFlag(String),
+    SetVariable(String, isize),
+    AddVariable(String, isize),
+    /// Adds the quest with this id to the quest log, unless it's already there or done
+    StartQuest(String),
+    /// Writes the game to disk
+    Save,
+    /// Ends the dialogue with a fight against this encounter table of the current map
+    Battle(String),
+}
+
+pub struct DialogueAssets {
+    database: Handle<DialogueDatabase>,
+}
+
+/// Story progress set by dialogue, kept in the save
+#[derive(Default)]
+pub struct StoryState {
+    pub(crate) flags: HashSet<String>,
+    /// Counters like favours owed or coins collected, missing ones read as 0
+    pub(crate) variables: HashMap<String, isize>,
+}
+
+/// Sent by a talking NPC to open the dialogue with this id
+pub struct StartDialogueEvent {
+    pub(crate) id: String,
+}
+
+/// The conversation on screen, only around while it's open
+pub struct ActiveDialogue {
+    dialogue: String,
+    /// Node still to be entered, set when the dialogue opens so its actions run in dialogue_input
+    pending: Option<String>,
+    node: String,
+    page: usize,
+    /// Index into the choices the player gets to see, not all of the node's choices
+    choice: usize,
+}
+
+/// Box showing one page of a node, revealing its text a character at a time
+#[derive(Component)]
+pub struct DialogueBox {
+    node: String,
+    page: usize,
+    /// Ascii text entities of the wrapped page, their children are the characters
+    lines: Vec<Entity>,
+    /// Ascii text entities of the choices, only shown once the page is out
+    choices: Vec<Entity>,
+    length: usize,
+    revealed: f32,
+    cursor: Entity,
+    more: Entity,
+    height: f32,
+}
+
+/// What conditions are checked against
+struct DialogueContext<'a> {
+    level: usize,
+    inventory: &'a Inventory,
+    story: &'a StoryState,
+    quests: &'a QuestLog,
+    hurt: bool,
+}
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<DialogueDatabase>()
+            .add_asset_loader(
+                RonAssetLoader::<DialogueDatabase>::new(&["dialogue.ron"])
+                    .validated(DialogueDatabase::validate),
+            )
+            .add_event::<StartDialogueEvent>()
+            .init_resource::<StoryState>()
+            .add_startup_system(load_dialogue_database)
+            .add_system_set(SystemSet::on_enter(GameState::Overworld).with_system(load_story_state))
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(start_dialogue)
+                    .with_system(dialogue_input)
+                    .with_system(update_dialogue_box.after(dialogue_input)),
+            )
+            // Runs after the commands spawning a new box are applied, so no text flashes up early
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                reveal_dialogue_text.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+impl DialogueDatabase {
+    pub fn contains(&self, dialogue: &str) -> bool {
+        self.dialogues.contains_key(dialogue)
+    }
+
+    /// Every node a dialogue starts at or moves on to has to exist
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        for (id, dialogue) in self.dialogues.iter() {
+            if !dialogue.nodes.contains_key(&dialogue.start) {
+                bail!(
+                    "dialogue '{}' starts at unknown node '{}'",
+                    id,
+                    dialogue.start
+                );
+            }
+
+            for (name, node) in dialogue.nodes.iter() {
+                let targets = node
+                    .choices
+                    .iter()
+                    .filter_map(|choice| choice.next.as_ref())
+                    .chain(node.next.iter().map(|branch| &branch.node));
+                for target in targets {
+                    if !dialogue.nodes.contains_key(target) {
+                        bail!(
+                            "node '{}' of dialogue '{}' leads to unknown node '{}'",
+                            name,
+                            id,
+                            target
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DialogueCondition {
+    fn holds(&self, context: &DialogueContext) -> bool {
+        match self {
+            DialogueCondition::Level(level) => context.level >= *level,
+            DialogueCondition::HasItem(item) => context.inventory.count(item) > 0,
+            DialogueCondition::Flag(flag) => context.story.flags.contains(flag),
+            DialogueCondition::Variable(variable, at_least) => {
+                context.story.variable(variable) >= *at_least
+            }
+            DialogueCondition::QuestActive(quest) => context.quests.is_active(quest),
+            DialogueCondition::QuestDone(quest) => context.quests.is_done(quest),
+            DialogueCondition::Hurt => context.hurt,
+            DialogueCondition::Not(condition) => !condition.holds(context),
+        }
+    }
+}
+
+fn all_hold(conditions: &[DialogueCondition], context: &DialogueContext) -> bool {
+    conditions.iter().all(|condition| condition.holds(context))
+}
+
+impl DialogueNode {
+    fn visible_choices<'a>(&'a self, context: &DialogueContext) -> Vec<&'a DialogueChoice> {
+        self.choices
+            .iter()
+            .filter(|choice| all_hold(&choice.conditions, context))
+            .collect()
+    }
+
+    fn next_node(&self, context: &DialogueContext) -> Option<String> {
+        self.next
+            .iter()
+            .find(|branch| all_hold(&branch.conditions, context))
+            .map(|branch| branch.node.clone())
+    }
+}
+
+impl DialogueBox {
+    fn done(&self) -> bool {
+        self.revealed >= self.length as f32
+    }
+}
+
+fn load_dialogue_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(DialogueAssets {
+        database: assets.load(DIALOGUE_DATABASE_PATH),
+    });
+}
+
+impl StoryState {
+    pub fn variable(&self, variable: &str) -> isize {
+        self.variables.get(variable).copied().unwrap_or(0)
+    }
+}
+
+fn load_story_state(mut commands: Commands, loaded_game: Res<LoadedGame>) {
+    let story = match &loaded_game.0 {
+        Some(save) => StoryState {
+            flags: save.flags.iter().cloned().collect(),
+            variables: save
+                .variables
+                .iter()
+                .map(|(variable, value)| (variable.clone(), *value))
+                .collect(),
+        },
+        None => StoryState::default(),
+    };
+    commands.insert_resource(story);
+}
+
+fn start_dialogue(
+    mut commands: Commands,
+    mut ev_start_dialogue: EventReader<StartDialogueEvent>,
+    mut player_query: Query<&mut Player>,
+    dialogue_assets: Res<DialogueAssets>,
+    dialogue_databases: Res<Assets<DialogueDatabase>>,
+) {
+    for event in ev_start_dialogue.iter() {
+        let dialogue = match dialogue_databases
+            .get(&dialogue_assets.database)
+            .and_then(|database| database.dialogues.get(&event.id))
+        {
+            Some(dialogue) => dialogue,
+            None => {
+                error!("Tried to start unknown dialogue '{}'", event.id);
+                continue;
+            }
+        };
+
+        // The box shows up next frame, so the key that opened it doesn't skip the first page
+        player_query.single_mut().active = false;
+        commands.insert_resource(ActiveDialogue {
+            dialogue: event.id.clone(),
+            pending: Some(dialogue.start.clone()),
+            node: dialogue.start.clone(),
+            page: 0,
+            choice: 0,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn dialogue_input(
+    mut commands: Commands,
+    dialogue: Option<ResMut<ActiveDialogue>>,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut box_query: Query<&mut DialogueBox>,
+    mut player_query: Query<(&mut Player, &mut Inventory)>,
+    mut party_query: Query<
+        (&mut CombatStats, &mut StatusEffects),
+        Or<(With<Player>, With<PartyMember>)>,
+    >,
+    mut story: ResMut<StoryState>,
+    mut quests: ResMut<QuestLog>,
+    dialogue_assets: Res<DialogueAssets>,
+    dialogue_databases: Res<Assets<DialogueDatabase>>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapDefinition>>,
+    ascii: Res<AsciiSheet>,
+    mut ev_save_game: EventWriter<SaveGameEvent>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    let mut active = match dialogue {
+        Some(active) => active,
+        None => return,
+    };
+    let dialogue = match dialogue_databases
+        .get(&dialogue_assets.database)
+        .and_then(|database| database.dialogues.get(&active.dialogue))
+    {
+        Some(dialogue) => dialogue,
+        None => return,
+    };
+    let (mut player, mut inventory) = player_query.single_mut();
+
+    // Taken only when there is one, the box is redrawn whenever the dialogue is touched
+    let mut next = if active.pending.is_some() {
+        active.pending.take()
+    } else {
+        None
+    };
+    if next.is_none() {
+        // Nothing can be advanced before the box for the current page is up
+        let mut dialogue_box = match box_query.get_single_mut() {
+            Ok(dialogue_box)
+                if dialogue_box.node == active.node && dialogue_box.page == active.page =>
+            {
+                dialogue_box
+            }
+            _ => return,
+        };
+        if !dialogue_box.done() {
+            if keyboard.clear_just_pressed(KeyCode::E) {
+                dialogue_box.revealed = dialogue_box.length as f32;
+            }
+            return;
+        }
+
+        let node = &dialogue.nodes[&active.node];
+        let context = DialogueContext {
+            level: player.level,
+            inventory: &inventory,
+            story: &story,
+            quests: &quests,
+            hurt: party_query
+                .iter()
+                .any(|(stats, statuses)| is_hurt(stats, statuses)),
+        };
+        let choices = node.visible_choices(&context);
+
+        if active.page + 1 < node.pages.len() {
+            if keyboard.clear_just_pressed(KeyCode::E) {
+                active.page += 1;
+            }
+            return;
+        }
+
+        if !choices.is_empty() {
+            let count = choices.len() as isize;
+            let mut new_index = active.choice as isize;
+            if keyboard.just_pressed(KeyCode::W) {
+                new_index -= 1;
+            }
+            if keyboard.just_pressed(KeyCode::S) {
+                new_index += 1;
+            }
+            let new_index = ((new_index + count) % count) as usize;
+            if new_index != active.choice {
+                active.choice = new_index;
+            }
+        }
+
+        if !keyboard.clear_just_pressed(KeyCode::E) {
+            return;
+        }
+        next = match choices.get(active.choice) {
+            Some(choice) => choice.next.clone(),
+            None => node.next_node(&context),
+        };
+    }
+
+    // Nodes without anything to show are passed straight through
+    for _ in 0..MAX_SILENT_NODES {
+        let id = match next {
+            Some(id) => id,
+            None => break,
+        };
+        let node = match dialogue.nodes.get(&id) {
+            Some(node) => node,
+            None => {
+                error!("Dialogue '{}' has no node '{}'", active.dialogue, id);
+                break;
+            }
+        };
+
+        for action in node.actions.iter() {
+            match action {
+                DialogueAction::Heal => {
+                    for (mut stats, mut statuses) in party_query.iter_mut() {
+                        stats.health = stats.max_health;
+                        stats.mp = stats.max_mp;
+                        statuses.clear();
+                    }
+                }
+                DialogueAction::GiveItem(item, count) => {
+                    let left_over = inventory.add(item, *count);
+                    if left_over > 0 {
+                        ev_text_popup.send(CreateTextPopupEvent {
+                            text: format!("Your inventory is full, {} left behind.", left_over),
+                            position: TextPopupPosition::Left,
+                            duration: 2.0,
+                            ..default()
+                        });
+                    }
+                }
+                DialogueAction::SetFlag(flag) => {
+                    story.flags.insert(flag.clone());
+                }
+                DialogueAction::SetVariable(variable, value) => {
+                    story.variables.insert(variable.clone(), *value);
+                }
+                DialogueAction::AddVariable(variable, amount) => {
+                    *story.variables.entry(variable.clone()).or_insert(0) += amount;
+                }
+                DialogueAction::StartQuest(quest) => quests.start(quest),
+                DialogueAction::Save => ev_save_game.send(SaveGameEvent),
+                DialogueAction::Battle(table) => {
+                    match current_map.encounter_table(&maps, table) {
+                        Some(table) => {
+                            commands.insert_resource(PendingEncounter {
+                                table: table.clone(),
+                                boss: None,
+                                enemy_fled: false,
+                            });
+                            create_fadeout(&mut commands, Some(GameState::Combat), &ascii);
+                        }
+                        None => {
+                            error!("Dialogue battle with unknown encounter table '{}'", table);
+                            player.active = true;
+                        }
+                    }
+                    // The player stays frozen until the fight takes over
+                    commands.remove_resource::<ActiveDialogue>();
+                    return;
+                }
+            }
+        }
+
+        let context = DialogueContext {
+            level: player.level,
+            inventory: &inventory,
+            story: &story,
+            quests: &quests,
+            hurt: party_query
+                .iter()
+                .any(|(stats, statuses)| is_hurt(stats, statuses)),
+        };
+        if !node.pages.is_empty() || !node.visible_choices(&context).is_empty() {
+            active.node = id;
+            active.page = 0;
+            active.choice = 0;
+            return;
+        }
+        next = node.next_node(&context);
+    }
+
+    commands.remove_resource::<ActiveDialogue>();
+    player.active = true;
+}
+
+fn is_hurt(stats: &CombatStats, statuses: &StatusEffects) -> bool {
+    stats.health < stats.max_health || stats.mp < stats.max_mp || !statuses.is_empty()
+}
+
+/// Respawns the box whenever the dialogue moves to another page, and takes it away once it's over
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_dialogue_box(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    nine_slice_indices: Res<NineSliceIndices>,
+    dialogue: Option<Res<ActiveDialogue>>,
+    box_query: Query<(Entity, &DialogueBox)>,
+    player_query: Query<(&Player, &Inventory)>,
+    party_query: Query<(&CombatStats, &StatusEffects), Or<(With<Player>, With<PartyMember>)>>,
+    story: Res<StoryState>,
+    quests: Res<QuestLog>,
+    dialogue_assets: Res<DialogueAssets>,
+    dialogue_databases: Res<Assets<DialogueDatabase>>,
+) {
+    let active = dialogue.filter(|active| active.pending.is_none());
+    let mut up_to_date = false;
+    for (entity, dialogue_box) in box_query.iter() {
+        match &active {
+            Some(active)
+                if dialogue_box.node == active.node && dialogue_box.page == active.page =>
+            {
+                up_to_date = true
+            }
+            _ => commands.entity(entity).despawn_recursive(),
+        }
+    }
+    if up_to_date {
+        return;
+    }
+
+    let active = match active {
+        Some(active) => active,
+        None => return,
+    };
+    let node = match dialogue_databases
+        .get(&dialogue_assets.database)
+        .and_then(|database| database.dialogues.get(&active.dialogue))
+        .and_then(|dialogue| dialogue.nodes.get(&active.node))
+    {
+        Some(node) => node,
+        None => return,
+    };
+
+    let (player, inventory) = player_query.single();
+    let context = DialogueContext {
+        level: player.level,
+        inventory,
+        story: &story,
+        quests: &quests,
+        hurt: party_query
+            .iter()
+            .any(|(stats, statuses)| is_hurt(stats, statuses)),
+    };
+    let choices = if active.page + 1 >= node.pages.len() {
+        node.visible_choices(&context)
+    } else {
+        Vec::new()
+    };
+
+    // A margin and the border on each side of the text
+    let width = DIALOGUE_BOX_WIDTH as f32;
+    let text = node
+        .pages
+        .get(active.page)
+        .map_or_else(Vec::new, |page| wrap_text(page, DIALOGUE_BOX_WIDTH - 4));
+    // The choices get a blank line between them and the text
+    let gap = usize::from(!text.is_empty() && !choices.is_empty());
+    let height = (text.len() + gap + choices.len() + 2) as f32;
+    let line_y = |line: usize| (height / 2.0 - 1.5 - line as f32) * TILE_SIZE;
+    let left = (-width / 2.0 + 2.0) * TILE_SIZE;
+
+    let mut children = vec![spawn_nine_slice(
+        &mut commands,
+        &ascii,
+        &nine_slice_indices,
+        width,
+        height,
+    )];
+    let lines: Vec<Entity> = text
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            spawn_ascii_text(&mut commands, &ascii, line, Vec3::new(left, line_y(i), 1.0))
+        })
+        .collect();
+    let choice_lines: Vec<Entity> = choices
+        .iter()
+        .enumerate()
+        .map(|(i, choice)| {
+            spawn_ascii_text(
+                &mut commands,
+                &ascii,
+                &choice.text,
+                Vec3::new(left + TILE_SIZE, line_y(text.len() + gap + i), 1.0),
+            )
+        })
+        .collect();
+
+    // ► and ▼ in the ascii sheet, shown by reveal_dialogue_text once the text is out
+    let cursor = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        16,
+        Color::RED,
+        Vec3::new(left, line_y(text.len() + gap), 1.0),
+        Vec3::splat(1.0),
+    );
+    let more = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        31,
+        Color::RED,
+        Vec3::new(
+            (width / 2.0 - 1.5) * TILE_SIZE,
+            (-height / 2.0 + 0.5) * TILE_SIZE,
+            2.0,
+        ),
+        Vec3::splat(1.0),
+    );
+    children.extend(lines.iter().chain(choice_lines.iter()));
+    children.push(cursor);
+    children.push(more);
+
+    commands
+        .spawn()
+        .insert(Transform::default())
+        .insert(GlobalTransform::default())
+        .insert(Name::new("DialogueBox"))
+        .insert(DialogueBox {
+            node: active.node.clone(),
+            page: active.page,
+            lines,
+            choices: choice_lines,
+            length: text.iter().map(|line| line.len()).sum(),
+            revealed: 0.0,
+            cursor,
+            more,
+            height,
+        })
+        .push_children(&children);
+}
+
+/// Reveals the page a character at a time, then shows the choices or the "more" arrow
+#[allow(clippy::type_complexity)]
+fn reveal_dialogue_text(
+    dialogue: Option<Res<ActiveDialogue>>,
+    mut box_query: Query<(&mut DialogueBox, &mut Transform)>,
+    camera_query: Query<&Transform, (With<Camera2d>, Without<DialogueBox>)>,
+    children_query: Query<&Children>,
+    mut sprite_query: Query<
+        (&mut Visibility, &mut Transform),
+        (Without<DialogueBox>, Without<Camera2d>),
+    >,
+    time: Res<Time>,
+) {
+    let (active, (mut dialogue_box, mut transform)) = match (dialogue, box_query.get_single_mut()) {
+        (Some(active), Ok(dialogue_box)) => (active, dialogue_box),
+        _ => return,
+    };
+
+    // Sits at the bottom of the screen however the camera moves or zooms
+    if let Ok(camera_transform) = camera_query.get_single() {
+        let offset = Vec3::new(
+            0.0,
+            -1.0 + dialogue_box.height * TILE_SIZE / 2.0 + 0.04,
+            0.0,
+        );
+        transform.translation = (camera_transform.translation + offset * camera_transform.scale)
+            .truncate()
+            .extend(110.0);
+        transform.scale = camera_transform.scale;
+    }
+
+    if !dialogue_box.done() {
+        dialogue_box.revealed += time.delta_seconds() * DIALOGUE_TEXT_SPEED;
+    }
+    let done = dialogue_box.done();
+
+    let mut shown = 0;
+    for line in dialogue_box.lines.iter() {
+        for character in children_query
+            .get(*line)
+            .map_or(&[][..], |children| &**children)
+        {
+            if let Ok((mut visibility, _)) = sprite_query.get_mut(*character) {
+                let visible = (shown as f32) < dialogue_box.revealed;
+                if visibility.is_visible != visible {
+                    visibility.is_visible = visible;
+                }
+            }
+            shown += 1;
+        }
+    }
+    for line in dialogue_box.choices.iter() {
+        for character in children_query
+            .get(*line)
+            .map_or(&[][..], |children| &**children)
+        {
+            if let Ok((mut visibility, _)) = sprite_query.get_mut(*character) {
+                if visibility.is_visible != done {
+                    visibility.is_visible = done;
+                }
+            }
+        }
+    }
+
+    let has_choices = !dialogue_box.choices.is_empty();
+    if let Ok((mut visibility, mut cursor_transform)) = sprite_query.get_mut(dialogue_box.cursor) {
+        visibility.is_visible = done && has_choices;
+        // The choices take up the last lines of the box
+        let height = dialogue_box.height;
+        let line = (height - 2.0) as usize - dialogue_box.choices.len() + active.choice;
+        cursor_transform.translation.y = (height / 2.0 - 1.5 - line as f32) * TILE_SIZE;
+    }
+    if let Ok((mut visibility, _)) = sprite_query.get_mut(dialogue_box.more) {
+        let blink_on =
+            time.seconds_since_startup() as f32 % MORE_BLINK_PERIOD < MORE_BLINK_PERIOD / 2.0;
+        visibility.is_visible = done && !has_choices && blink_on;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::ItemDatabase;
+
+    /// Only the ids of the other data files, their contents are checked where they're loaded
+    #[derive(Deserialize)]
+    struct Ids {
+        #[serde(default)]
+        quests: HashMap<String, ron::Value>,
+        #[serde(default)]
+        encounters: HashMap<String, ron::Value>,
+    }
+
+    #[test]
+    fn shipped_dialogue_is_valid() {
+        let database: DialogueDatabase =
+            ron::from_str(include_str!("../assets/data/dialogue.ron")).unwrap();
+        if let Err(err) = database.validate() {
+            panic!("{:#}", err);
+        }
+
+        let items: ItemDatabase = ron::from_str(include_str!("../assets/data/items.ron")).unwrap();
+        let quests: Ids = ron::from_str(include_str!("../assets/data/quests.ron")).unwrap();
+        // Battles use the table of whichever map the NPC stands on, so any map will do
+        let mut tables = HashSet::default();
+        for entry in std::fs::read_dir("assets/maps").unwrap() {
+            let map: Ids =
+                ron::from_str(&std::fs::read_to_string(entry.unwrap().path()).unwrap()).unwrap();
+            tables.extend(map.encounters.into_iter().map(|(table, _)| table));
+        }
+
+        for (id, dialogue) in database.dialogues.iter() {
+            for (name, node) in dialogue.nodes.iter() {
+                let conditions = node
+                    .choices
+                    .iter()
+                    .flat_map(|choice| choice.conditions.iter())
+                    .chain(node.next.iter().flat_map(|branch| branch.conditions.iter()));
+                for condition in conditions {
+                    let mut condition = condition;
+                    while let DialogueCondition::Not(inner) = condition {
+                        condition = inner;
+                    }
+                    match condition {
+                        DialogueCondition::HasItem(item) => {
+                            assert!(
+                                items.get(item).is_some(),
+                                "{}/{}: item '{}'",
+                                id,
+                                name,
+                                item
+                            )
+                        }
+                        DialogueCondition::QuestActive(quest)
+                        | DialogueCondition::QuestDone(quest) => assert!(
+                            quests.quests.contains_key(quest),
+                            "{}/{}: quest '{}'",
+                            id,
+                            name,
+                            quest
+                        ),
+                        _ => {}
+                    }
+                }
+
+                for action in node.actions.iter() {
+                    match action {
+                        DialogueAction::GiveItem(item, _) => {
+                            assert!(
+                                items.get(item).is_some(),
+                                "{}/{}: item '{}'",
+                                id,
+                                name,
+                                item
+                            )
+                        }
+                        DialogueAction::StartQuest(quest) => assert!(
+                            quests.quests.contains_key(quest),
+                            "{}/{}: quest '{}'",
+                            id,
+                            name,
+                            quest
+                        ),
+                        DialogueAction::Battle(table) => {
+                            assert!(tables.contains(table), "{}/{}: table '{}'", id, name, table)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_next_node_is_rejected() {
+        let database: DialogueDatabase = ron::from_str(
+            r#"(dialogues: {"npc": (start: "a", nodes: {"a": (next: [(node: "b")])})})"#,
+        )
+        .unwrap();
+        assert!(database.validate().is_err());
+    }
+}
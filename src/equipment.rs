@@ -0,0 +1,426 @@
+use crate::combat::CombatStats;
+use crate::game_ui::{CreateTextPopupEvent, TextPopupPosition, UiAssets};
+use crate::inventory::{item_name, Inventory, ItemAssets, ItemDatabase, ItemEffect};
+use crate::player::Player;
+use crate::status::StatusEffects;
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct EquipmentPlugin;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+    Accessory,
+}
+
+pub const EQUIP_SLOTS: [EquipSlot; 3] = [EquipSlot::Weapon, EquipSlot::Armor, EquipSlot::Accessory];
+
+/// Item ids worn in each slot
+#[derive(Component, Serialize, Deserialize, Clone, Default)]
+pub struct Equipment {
+    weapon: Option<String>,
+    armor: Option<String>,
+    accessory: Option<String>,
+}
+
+/// Stats granted by worn equipment and status effects, kept apart from the base
+/// `CombatStats` so that taking an item off or a buff running out never loses anything
+#[derive(Component, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct StatBonus {
+    pub(crate) attack: isize,
+    pub(crate) defense: isize,
+    pub(crate) speed: isize,
+}
+
+#[derive(Component)]
+pub struct EquipScreen;
+
+#[derive(Component)]
+pub struct EquipScreenText;
+
+/// Slot picked on the equip screen and which candidate item is previewed for it
+#[derive(Default)]
+pub struct EquipSelection {
+    slot: usize,
+    candidate: usize,
+}
+
+impl Plugin for EquipmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EquipSelection>()
+            .add_system(refresh_stat_bonus_on_load.before(update_stat_bonus))
+            .add_system(update_stat_bonus)
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(toggle_equip_screen)
+                    .with_system(equip_screen_input.after(toggle_equip_screen))
+                    .with_system(update_equip_screen.after(equip_screen_input)),
+            );
+    }
+}
+
+impl Equipment {
+    pub fn starting() -> Self {
+        Equipment {
+            weapon: Some("wooden_sword".to_string()),
+            ..default()
+        }
+    }
+
+    pub fn get(&self, slot: EquipSlot) -> Option<&String> {
+        match slot {
+            EquipSlot::Weapon => self.weapon.as_ref(),
+            EquipSlot::Armor => self.armor.as_ref(),
+            EquipSlot::Accessory => self.accessory.as_ref(),
+        }
+    }
+
+    /// Puts the item into the slot, returns whatever was worn there before
+    pub fn equip(&mut self, slot: EquipSlot, item: Option<String>) -> Option<String> {
+        let worn = match slot {
+            EquipSlot::Weapon => &mut self.weapon,
+            EquipSlot::Armor => &mut self.armor,
+            EquipSlot::Accessory => &mut self.accessory,
+        };
+        std::mem::replace(worn, item)
+    }
+
+    /// Swaps an item that was already taken out of the inventory into the slot, the worn one goes
+    /// back into the inventory. If that doesn't fit everything is put back and its id returned
+    pub fn wear(
+        &mut self,
+        slot: EquipSlot,
+        item: Option<String>,
+        inventory: &mut Inventory,
+    ) -> Result<(), String> {
+        let previous = match self.equip(slot, item.clone()) {
+            Some(previous) => previous,
+            None => return Ok(()),
+        };
+        if inventory.add(&previous, 1) == 0 {
+            return Ok(());
+        }
+
+        self.equip(slot, Some(previous.clone()));
+        if let Some(item) = item {
+            inventory.add(&item, 1);
+        }
+        Err(previous)
+    }
+
+    pub fn bonus(&self, database: &ItemDatabase) -> StatBonus {
+        EQUIP_SLOTS
+            .iter()
+            .filter_map(|&slot| self.get(slot))
+            .filter_map(
+                |item| match database.get(item).map(|definition| &definition.effect) {
+                    Some(ItemEffect::Equip(_, bonus)) => Some(*bonus),
+                    _ => None,
+                },
+            )
+            .fold(StatBonus::default(), |total, bonus| total + bonus)
+    }
+}
+
+impl std::ops::Add for StatBonus {
+    type Output = StatBonus;
+
+    fn add(self, other: StatBonus) -> StatBonus {
+        StatBonus {
+            attack: self.attack + other.attack,
+            defense: self.defense + other.defense,
+            speed: self.speed + other.speed,
+        }
+    }
+}
+
+impl CombatStats {
+    /// Base stats with the equipment bonus on top, what fights actually use
+    pub fn derived(&self, bonus: Option<&StatBonus>) -> CombatStats {
+        let bonus = bonus.copied().unwrap_or_default();
+        CombatStats {
+            attack: self.attack + bonus.attack,
+            defense: self.defense + bonus.defense,
+            speed: self.speed + bonus.speed,
+            ..self.clone()
+        }
+    }
+}
+
+/// Equipment spawned before the item database finished loading got no bonus yet, so it is
+/// marked changed once the database is there
+fn refresh_stat_bonus_on_load(
+    mut ev_asset: EventReader<AssetEvent<ItemDatabase>>,
+    mut query: Query<&mut Equipment>,
+) {
+    let loaded = ev_asset.iter().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Created { .. } | AssetEvent::Modified { .. }
+        )
+    });
+    if !loaded {
+        return;
+    }
+
+    for mut equipment in query.iter_mut() {
+        equipment.set_changed();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_stat_bonus(
+    mut query: Query<
+        (Option<&Equipment>, Option<&StatusEffects>, &mut StatBonus),
+        Or<(Changed<Equipment>, Changed<StatusEffects>)>,
+    >,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+) {
+    let database = match item_databases.get(&item_assets.database) {
+        Some(database) => database,
+        None => return,
+    };
+
+    for (equipment, statuses, mut bonus) in query.iter_mut() {
+        let equipment_bonus =
+            equipment.map_or_else(StatBonus::default, |equipment| equipment.bonus(database));
+        let status_bonus = statuses.map_or_else(StatBonus::default, StatusEffects::bonus);
+        *bonus = equipment_bonus + status_bonus;
+    }
+}
+
+/// What can go into the slot: the worn item first, then taking it off,
+/// then anything fitting from the inventory
+fn slot_candidates(
+    slot: EquipSlot,
+    equipment: &Equipment,
+    inventory: &Inventory,
+    database: &ItemDatabase,
+) -> Vec<Option<String>> {
+    let mut candidates = vec![equipment.get(slot).cloned()];
+    if equipment.get(slot).is_some() {
+        candidates.push(None);
+    }
+
+    candidates.extend(inventory.items().iter().filter_map(|stack| {
+        match database
+            .get(&stack.item)
+            .map(|definition| &definition.effect)
+        {
+            Some(ItemEffect::Equip(item_slot, _)) if *item_slot == slot => {
+                Some(Some(stack.item.clone()))
+            }
+            _ => None,
+        }
+    }));
+    candidates
+}
+
+fn toggle_equip_screen(
+    mut commands: Commands,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut player_query: Query<&mut Player>,
+    screen_query: Query<Entity, With<EquipScreen>>,
+    mut selection: ResMut<EquipSelection>,
+    ui_assets: Res<UiAssets>,
+) {
+    if !keyboard.clear_just_pressed(KeyCode::Q) {
+        return;
+    }
+
+    let mut player = player_query.single_mut();
+    if let Ok(screen) = screen_query.get_single() {
+        commands.entity(screen).despawn_recursive();
+        player.active = true;
+    } else if player.active {
+        player.active = false;
+        *selection = EquipSelection::default();
+        spawn_equip_screen(&mut commands, &ui_assets);
+    }
+}
+
+fn spawn_equip_screen(commands: &mut Commands, ui_assets: &UiAssets) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Percent(2.0),
+                    top: Val::Percent(4.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(40.0), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Percent(1.0)),
+                ..default()
+            },
+            color: Color::rgba(0.05, 0.05, 0.2, 0.9).into(),
+            ..default()
+        })
+        .insert(Name::new("EquipScreen"))
+        .insert(EquipScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Equipment",
+                    TextStyle {
+                        font: ui_assets.font_bold.clone(),
+                        font_size: 26.0,
+                        color: Color::GOLD,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+
+            // Filled in by update_equip_screen
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: ui_assets.font.clone(),
+                            font_size: 24.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                        default(),
+                    ),
+                    ..default()
+                })
+                .insert(EquipScreenText);
+        });
+}
+
+fn equip_screen_input(
+    keyboard: Res<Input<KeyCode>>,
+    mut player_query: Query<(&mut Inventory, &mut Equipment), With<Player>>,
+    screen_query: Query<&EquipScreen>,
+    mut selection: ResMut<EquipSelection>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    let database = match item_databases.get(&item_assets.database) {
+        Some(database) => database,
+        None => return,
+    };
+    if screen_query.is_empty() {
+        return;
+    }
+
+    let (mut inventory, mut equipment) = player_query.single_mut();
+
+    let slot_count = EQUIP_SLOTS.len() as isize;
+    let mut new_slot = selection.slot as isize;
+    if keyboard.just_pressed(KeyCode::W) {
+        new_slot -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::S) {
+        new_slot += 1;
+    }
+    let new_slot = ((new_slot + slot_count) % slot_count) as usize;
+    if new_slot != selection.slot {
+        selection.slot = new_slot;
+        selection.candidate = 0;
+    }
+
+    let slot = EQUIP_SLOTS[selection.slot];
+    let candidates = slot_candidates(slot, &equipment, &inventory, database);
+    let candidate_count = candidates.len() as isize;
+    let mut new_candidate = selection.candidate as isize;
+    if keyboard.just_pressed(KeyCode::A) {
+        new_candidate -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::D) {
+        new_candidate += 1;
+    }
+    selection.candidate = ((new_candidate + candidate_count) % candidate_count) as usize;
+
+    // The first candidate is what's already worn
+    if keyboard.just_pressed(KeyCode::E) && selection.candidate != 0 {
+        let item = candidates[selection.candidate].clone();
+        if let Some(item) = &item {
+            inventory.remove_one(item);
+        }
+        match equipment.wear(slot, item, &mut inventory) {
+            Ok(()) => selection.candidate = 0,
+            Err(previous) => ev_text_popup.send(CreateTextPopupEvent {
+                text: format!(
+                    "No room in the inventory for {}.",
+                    item_name(Some(database), &previous)
+                ),
+                position: TextPopupPosition::Center,
+                duration: 2.0,
+                ..default()
+            }),
+        }
+    }
+}
+
+fn update_equip_screen(
+    player_query: Query<(&Inventory, &Equipment, &CombatStats), With<Player>>,
+    mut text_query: Query<&mut Text, With<EquipScreenText>>,
+    selection: Res<EquipSelection>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+) {
+    let (mut text, database) = match (
+        text_query.get_single_mut(),
+        item_databases.get(&item_assets.database),
+    ) {
+        (Ok(text), Some(database)) => (text, database),
+        _ => return,
+    };
+
+    let (inventory, equipment, stats) = player_query.single();
+    let name = |item: Option<&String>| match item {
+        Some(item) => item_name(Some(database), item),
+        None => "(none)".to_string(),
+    };
+
+    let mut lines = Vec::new();
+    for (i, &slot) in EQUIP_SLOTS.iter().enumerate() {
+        let cursor = if i == selection.slot { ">" } else { " " };
+        lines.push(format!(
+            "{} {:?}: {}",
+            cursor,
+            slot,
+            name(equipment.get(slot))
+        ));
+    }
+
+    // Preview the stats as if the highlighted candidate was worn
+    let slot = EQUIP_SLOTS[selection.slot];
+    let candidates = slot_candidates(slot, equipment, inventory, database);
+    let candidate = candidates
+        .get(selection.candidate)
+        .cloned()
+        .unwrap_or_default();
+    let mut preview = equipment.clone();
+    lines.push(String::new());
+    lines.push(format!("< {} >", name(candidate.as_ref())));
+    preview.equip(slot, candidate);
+
+    let current = stats.derived(Some(&equipment.bonus(database)));
+    let previewed = stats.derived(Some(&preview.bonus(database)));
+    for (label, from, to) in [
+        ("ATK", current.attack, previewed.attack),
+        ("DEF", current.defense, previewed.defense),
+        ("SPD", current.speed, previewed.speed),
+    ] {
+        let delta = match to - from {
+            0 => String::new(),
+            delta => format!(" ({:+})", delta),
+        };
+        lines.push(format!("{} {} -> {}{}", label, from, to, delta));
+    }
+
+    lines.push(String::new());
+    lines.push("W, S: slot  A, D: item  E: equip".to_string());
+    text.sections[0].value = lines.join("\n");
+}
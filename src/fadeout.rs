@@ -1,5 +1,6 @@
 use crate::ascii::AsciiSheet;
 use crate::start_menu::StartMenuButton;
+use crate::tilemap::{ChangeMapEvent, WarpTile};
 use crate::GameState;
 use bevy::prelude::*;
 
@@ -10,6 +11,7 @@ struct ScreenFade {
     alpha: f32,
     sent: bool,
     next_state: Option<GameState>,
+    warp: Option<WarpTile>,
     timer: Timer,
 }
 
@@ -40,6 +42,7 @@ fn fadeout(
     mut commands: Commands,
     mut fade_query: Query<(Entity, &mut ScreenFade, &mut TextureAtlasSprite)>,
     mut state: ResMut<State<GameState>>,
+    mut ev_change_map: EventWriter<ChangeMapEvent>,
     time: Res<Time>,
 ) {
     for (entity, mut fade, mut sprite) in fade_query.iter_mut() {
@@ -53,10 +56,14 @@ fn fadeout(
         sprite.color.set_a(fade.alpha);
 
         if fade.timer.percent() > 0.5 && !fade.sent {
-            match fade.next_state {
-                Some(GameState::StartMenu) => state.replace(GameState::StartMenu).unwrap(),
-                Some(next_state) => state.push(next_state).unwrap(),
-                None => state.pop().unwrap(),
+            if let Some(warp) = fade.warp.take() {
+                ev_change_map.send(ChangeMapEvent { warp });
+            } else {
+                match fade.next_state {
+                    Some(GameState::StartMenu) => state.replace(GameState::StartMenu).unwrap(),
+                    Some(next_state) => state.push(next_state).unwrap(),
+                    None => state.pop().unwrap(),
+                }
             }
             fade.sent = true;
         }
@@ -71,6 +78,20 @@ pub fn create_fadeout(
     commands: &mut Commands,
     next_state: Option<GameState>,
     ascii: &Res<AsciiSheet>,
+) {
+    spawn_fadeout(commands, next_state, None, ascii);
+}
+
+/// Fades out, swaps the overworld map for the warp destination and fades back in
+pub fn create_warp_fadeout(commands: &mut Commands, warp: WarpTile, ascii: &Res<AsciiSheet>) {
+    spawn_fadeout(commands, None, Some(warp), ascii);
+}
+
+fn spawn_fadeout(
+    commands: &mut Commands,
+    next_state: Option<GameState>,
+    warp: Option<WarpTile>,
+    ascii: &Res<AsciiSheet>,
 ) {
     let mut sprite = TextureAtlasSprite::new(0);
     sprite.color = Color::rgba(0.1, 0.1, 0.15, 0.0);
@@ -90,6 +111,7 @@ pub fn create_fadeout(
             alpha: 0.0,
             sent: false,
             next_state,
+            warp,
             timer: Timer::from_seconds(0.7, false),
         })
         .insert(Name::new("Fadeout"));
@@ -1,11 +1,20 @@
-use crate::combat::{ExpReceivedEvent, LevelupEvent};
+use crate::combat::{CombatStats, ExpReceivedEvent, LevelupEvent};
+use crate::inventory::Wallet;
+use crate::pause_menu::not_paused;
+use crate::save::LoadedGame;
 use crate::GameState;
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
+use bevy::ui::FocusPolicy;
 use bevy_inspector_egui::Inspectable;
 
 pub struct GameUiPlugin;
 
+/// Most popups shown at once in one spot, the rest wait in the queue
+const MAX_STACKED_POPUPS: usize = 3;
+/// Seconds a dismissed or pushed out popup takes to fade away
+const POPUP_FADE_OUT: f32 = 0.4;
+
 pub struct UiAssets {
     pub(crate) font: Handle<Font>,
     pub(crate) font_bold: Handle<Font>,
@@ -16,18 +25,41 @@ pub struct UiAssets {
 #[derive(Component)]
 pub struct LevelupText;
 
+#[derive(Component)]
+pub struct GoldText;
+
 #[derive(Component)]
 pub struct TextPopup {
+    position: TextPopupPosition,
+    priority: PopupPriority,
+    /// Stays up without a timer until the player dismisses it
+    sticky: bool,
     timer: Timer,
     when_start_fading: f32,
 }
 
+/// Column the popups of one spot are stacked in, oldest on top
+#[derive(Component)]
+pub struct PopupSlot(TextPopupPosition);
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum TextPopupPosition {
     Left,
     Center,
 }
 
+/// Decides which waiting popup goes first, a higher one also pushes out a lower one when the spot is full
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum PopupPriority {
+    Low,
+    Normal,
+    High,
+}
+
+/// Popups waiting for room in their spot, in the order they were sent
+#[derive(Default)]
+pub struct PopupQueue(Vec<CreateTextPopupEvent>);
+
 #[derive(Component, Inspectable)]
 pub struct ExpBar {
     width: f32,
@@ -36,10 +68,26 @@ pub struct ExpBar {
     progress_step: f32,
 }
 
+#[derive(Clone)]
 pub struct CreateTextPopupEvent {
     pub(crate) text: String,
     pub(crate) position: TextPopupPosition,
     pub(crate) duration: f32,
+    pub(crate) priority: PopupPriority,
+    /// Ignores the duration and stays up until dismissed with Space
+    pub(crate) sticky: bool,
+}
+
+impl Default for CreateTextPopupEvent {
+    fn default() -> Self {
+        CreateTextPopupEvent {
+            text: String::new(),
+            position: TextPopupPosition::Center,
+            duration: 2.0,
+            priority: PopupPriority::Normal,
+            sticky: false,
+        }
+    }
 }
 
 #[derive(Component)]
@@ -58,22 +106,30 @@ pub enum HealthBarType {
 impl Plugin for GameUiPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<CreateTextPopupEvent>()
+            .init_resource::<PopupQueue>()
             .add_startup_system(setup_ui)
+            // Going back to the title despawns everything, the slots included
+            .add_system_set(
+                SystemSet::on_enter(GameState::StartMenu).with_system(spawn_popup_slots),
+            )
             .add_system_set(
                 SystemSet::on_enter(GameState::Overworld)
                     .with_system(spawn_level_text)
+                    .with_system(spawn_gold_text)
                     .with_system(show_help_initially)
                     .with_system(spawn_exp_bar),
             )
-            .add_system_set(
-                SystemSet::on_update(GameState::Combat).with_system(handle_levelup_event),
-            )
             .add_system_set(
                 SystemSet::on_update(GameState::Overworld).with_system(show_help_on_button_press),
             )
+            .add_system(update_health_bars)
+            .add_system(update_gold_text)
+            .add_system(handle_levelup_event)
             .add_system(handle_exp_received_event)
             .add_system(handle_text_popup_event)
-            .add_system(update_text_popups);
+            .add_system(show_queued_popups.after(handle_text_popup_event))
+            .add_system(dismiss_sticky_popups)
+            .add_system(update_text_popups.with_run_criteria(not_paused));
     }
 }
 
@@ -124,6 +180,19 @@ pub fn create_health_bar(commands: &mut Commands, hb_type: HealthBarType, owner:
     health_bar_bg
 }
 
+/// Keeps every health bar in sync with the stats of its owner, whatever changed them
+fn update_health_bars(
+    stats_query: Query<&CombatStats, Changed<CombatStats>>,
+    mut health_bar_query: Query<(&mut Transform, &HealthBar)>,
+) {
+    for (mut transform, health_bar) in health_bar_query.iter_mut() {
+        if let Ok(stats) = stats_query.get(health_bar.entity) {
+            let health_percent = stats.health as f32 / stats.max_health as f32;
+            transform.scale = Vec3::new(health_percent, 1.0, 1.0);
+        }
+    }
+}
+
 fn show_help_initially(ev_text_popup: EventWriter<CreateTextPopupEvent>) {
     show_help(ev_text_popup);
 }
@@ -131,17 +200,24 @@ fn show_help_initially(ev_text_popup: EventWriter<CreateTextPopupEvent>) {
 fn show_help(mut ev_text_popup: EventWriter<CreateTextPopupEvent>) {
     let text = r"Controls:
   WASD: movement
-  Esc: go to menu
+  Esc: pause
   Up, Down, M: volume control
   Num+, Num-, Home: camera control
   E: interact
-  A, D: select option
-  H: show help"
+  I: inventory
+  L: quest log
+  Q: equipment
+  A, D: select option or target
+  W, S: select item
+  H: show help
+  Space: dismiss message"
         .to_string();
     ev_text_popup.send(CreateTextPopupEvent {
         text,
         position: TextPopupPosition::Left,
-        duration: 5.0,
+        priority: PopupPriority::Low,
+        sticky: true,
+        ..default()
     });
 }
 
@@ -156,10 +232,14 @@ fn show_help_on_button_press(
 
 fn update_text_popups(
     mut commands: Commands,
-    mut query: Query<(&Parent, &mut Text, &mut TextPopup)>,
+    mut query: Query<(Entity, &mut Text, &mut TextPopup)>,
     time: Res<Time>,
 ) {
-    for (parent_entity, mut text, mut popup) in query.iter_mut() {
+    for (entity, mut text, mut popup) in query.iter_mut() {
+        if popup.sticky {
+            continue;
+        }
+
         popup.timer.tick(time.delta());
         let percent_left = popup.timer.percent_left();
         if percent_left < popup.when_start_fading {
@@ -170,31 +250,128 @@ fn update_text_popups(
         }
 
         if popup.timer.just_finished() {
-            commands.entity(parent_entity.0).despawn_recursive();
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+impl TextPopup {
+    fn fading(&self) -> bool {
+        !self.sticky && self.timer.percent_left() < self.when_start_fading
+    }
+
+    /// Starts fading right away, also for sticky popups
+    fn fade_out(&mut self) {
+        if !self.fading() {
+            self.sticky = false;
+            self.timer = Timer::from_seconds(POPUP_FADE_OUT, false);
+            self.when_start_fading = 1.0;
         }
     }
 }
 
 fn handle_text_popup_event(
-    mut commands: Commands,
     mut ev_text_popup: EventReader<CreateTextPopupEvent>,
-    ui_assets: Res<UiAssets>,
-    popup_query: Query<&Parent, With<TextPopup>>,
+    mut popup_query: Query<(&Text, &mut TextPopup)>,
+    mut queue: ResMut<PopupQueue>,
 ) {
     for event in ev_text_popup.iter() {
-        // Destroy old popup
-        for popup_parent_node in popup_query.iter() {
-            commands.entity(popup_parent_node.0).despawn_recursive();
+        // Saying the same thing again only keeps the popup up for longer
+        let shown = popup_query.iter_mut().find(|(text, popup)| {
+            popup.position == event.position
+                && !popup.fading()
+                && text.sections[0].value == event.text
+        });
+        if let Some((_, mut popup)) = shown {
+            popup.timer.reset();
+            continue;
+        }
+
+        queue.0.push(event.clone());
+    }
+}
+
+/// Moves popups from the queue into their spot as long as there's room
+fn show_queued_popups(
+    mut commands: Commands,
+    ui_assets: Res<UiAssets>,
+    mut queue: ResMut<PopupQueue>,
+    slot_query: Query<(Entity, &PopupSlot)>,
+    mut popup_query: Query<&mut TextPopup>,
+) {
+    if queue.0.is_empty() {
+        return;
+    }
+
+    // Nothing would ever take these, so they'd pile up in the queue for good
+    queue.0.retain(|event| {
+        let has_slot = slot_query
+            .iter()
+            .any(|(_, PopupSlot(position))| *position == event.position);
+        if !has_slot {
+            error!("No {:?} popup slot for '{}'", event.position, event.text);
         }
+        has_slot
+    });
+
+    for (slot, PopupSlot(position)) in slot_query.iter() {
+        let mut shown = popup_query
+            .iter()
+            .filter(|popup| popup.position == *position)
+            .count();
+
+        loop {
+            // Highest priority first, the earliest among equals
+            let next = queue
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(_, event)| event.position == *position)
+                .min_by_key(|(_, event)| std::cmp::Reverse(event.priority))
+                .map(|(index, _)| index);
+            let index = match next {
+                Some(index) => index,
+                None => break,
+            };
+
+            if shown < MAX_STACKED_POPUPS {
+                let event = queue.0.remove(index);
+                create_text_popup(&mut commands, &ui_assets, slot, &event);
+                shown += 1;
+                continue;
+            }
+
+            // A full spot makes room for something more important by fading its least important popup
+            let priority = queue.0[index].priority;
+            let pushed_out = popup_query
+                .iter_mut()
+                .filter(|popup| {
+                    popup.position == *position
+                        && !popup.sticky
+                        && !popup.fading()
+                        && popup.priority < priority
+                })
+                .min_by_key(|popup| popup.priority);
+            if let Some(mut popup) = pushed_out {
+                popup.fade_out();
+            }
+            break;
+        }
+    }
+}
+
+fn dismiss_sticky_popups(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut popup_query: Query<&mut TextPopup>,
+) {
+    if !popup_query.iter().any(|popup| popup.sticky) {
+        return;
+    }
 
-        // Create new one
-        create_text_popup(
-            &mut commands,
-            &ui_assets,
-            event.text.as_str(),
-            event.position,
-            event.duration,
-        );
+    if keyboard.clear_just_pressed(KeyCode::Space) {
+        for mut popup in popup_query.iter_mut().filter(|popup| popup.sticky) {
+            popup.fade_out();
+        }
     }
 }
 
@@ -240,12 +417,18 @@ fn handle_levelup_event(
     mut levelup_text_query: Query<&mut Text, With<LevelupText>>,
 ) {
     for event in ev_levelup.iter() {
-        let mut levelup_text = levelup_text_query.single_mut();
-        levelup_text.sections[0].value = format!("Level {}", event.new_level)
+        if let Ok(mut levelup_text) = levelup_text_query.get_single_mut() {
+            levelup_text.sections[0].value = format!("Level {}", event.new_level)
+        }
     }
 }
 
-fn spawn_exp_bar(mut commands: Commands) {
+fn spawn_exp_bar(mut commands: Commands, loaded_game: Res<LoadedGame>) {
+    let exp_percentage = loaded_game
+        .0
+        .as_ref()
+        .map_or(0.0, |save| save.levelup_percentage());
+
     let style = Style {
         position_type: PositionType::Absolute,
         position: Rect {
@@ -255,7 +438,7 @@ fn spawn_exp_bar(mut commands: Commands) {
             bottom: Val::Percent(0.0),
         },
         size: Size {
-            width: Val::Percent(0.0),
+            width: Val::Percent(exp_percentage * 100.0),
             height: Val::Percent(1.0),
         },
         ..default()
@@ -269,14 +452,20 @@ fn spawn_exp_bar(mut commands: Commands) {
         })
         .insert(Name::new("ExpBar"))
         .insert(ExpBar {
-            width: 0.0,
-            target_width: 0.0,
+            width: exp_percentage,
+            target_width: exp_percentage,
             progress_duration: 0.6,
             progress_step: 0.0,
         });
 }
 
-fn spawn_level_text(mut commands: Commands, ui_assets: Res<UiAssets>) {
+fn spawn_level_text(
+    mut commands: Commands,
+    ui_assets: Res<UiAssets>,
+    loaded_game: Res<LoadedGame>,
+) {
+    let level = loaded_game.0.as_ref().map_or(1, |save| save.player.level);
+
     let text_style = TextStyle {
         font: ui_assets.font_bold.clone(),
         font_size: 20.0,
@@ -301,7 +490,7 @@ fn spawn_level_text(mut commands: Commands, ui_assets: Res<UiAssets>) {
 
     commands
         .spawn_bundle(TextBundle {
-            text: Text::with_section("Level 1", text_style, text_alignment),
+            text: Text::with_section(format!("Level {}", level), text_style, text_alignment),
             style,
             ..default()
         })
@@ -309,6 +498,52 @@ fn spawn_level_text(mut commands: Commands, ui_assets: Res<UiAssets>) {
         .insert(LevelupText);
 }
 
+/// Sits to the right of the level text at the bottom of the screen
+fn spawn_gold_text(mut commands: Commands, ui_assets: Res<UiAssets>, loaded_game: Res<LoadedGame>) {
+    let gold = loaded_game
+        .0
+        .as_ref()
+        .map_or(0, |save| save.player.wallet.gold);
+
+    let style = Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            left: Val::Percent(56.0),
+            bottom: Val::Percent(1.1),
+            ..default()
+        },
+        ..default()
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(
+                format!("{} G", gold),
+                TextStyle {
+                    font: ui_assets.font_bold.clone(),
+                    font_size: 20.0,
+                    color: Color::GOLD,
+                },
+                default(),
+            ),
+            style,
+            ..default()
+        })
+        .insert(Name::new("GoldText"))
+        .insert(GoldText);
+}
+
+fn update_gold_text(
+    wallet_query: Query<&Wallet, Changed<Wallet>>,
+    mut gold_text_query: Query<&mut Text, With<GoldText>>,
+) {
+    if let (Ok(wallet), Ok(mut text)) =
+        (wallet_query.get_single(), gold_text_query.get_single_mut())
+    {
+        text.sections[0].value = format!("{} G", wallet.gold);
+    }
+}
+
 fn setup_ui(mut commands: Commands, assets: Res<AssetServer>) {
     let ui_assets = UiAssets {
         font_bold: assets.load("fonts/QuattrocentoSans-Bold.ttf"),
@@ -320,12 +555,38 @@ fn setup_ui(mut commands: Commands, assets: Res<AssetServer>) {
     commands.spawn_bundle(UiCameraBundle::default());
 }
 
+fn spawn_popup_slots(mut commands: Commands, mut queue: ResMut<PopupQueue>) {
+    // Whatever was still waiting belongs to the game that was just left
+    queue.0.clear();
+
+    for position in [TextPopupPosition::Left, TextPopupPosition::Center] {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_items: match position {
+                        TextPopupPosition::Left => AlignItems::FlexStart,
+                        TextPopupPosition::Center => AlignItems::Center,
+                    },
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    ..default()
+                },
+                color: Color::NONE.into(),
+                // Covers the whole screen, so it mustn't swallow clicks meant for buttons
+                focus_policy: FocusPolicy::Pass,
+                ..default()
+            })
+            .insert(Name::new(format!("PopupSlot{:?}", position)))
+            .insert(PopupSlot(position));
+    }
+}
+
 pub fn create_text_popup(
     commands: &mut Commands,
     ui_assets: &UiAssets,
-    text: &str,
-    position: TextPopupPosition,
-    duration: f32,
+    slot: Entity,
+    event: &CreateTextPopupEvent,
 ) {
     let text_style = TextStyle {
         font: ui_assets.font.clone(),
@@ -338,19 +599,10 @@ pub fn create_text_popup(
         horizontal: HorizontalAlign::Left,
     };
 
-    let node_style = Style {
-        position_type: PositionType::Absolute,
-        flex_direction: FlexDirection::Column,
-        justify_content: JustifyContent::FlexEnd,
-        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-        ..default()
-    };
-
     let style = Style {
         position_type: PositionType::Relative,
-        align_self: AlignSelf::Center,
         margin: Rect {
-            left: match position {
+            left: match event.position {
                 TextPopupPosition::Left => Val::Percent(1.0),
                 _ => Val::Auto,
             },
@@ -361,23 +613,20 @@ pub fn create_text_popup(
         ..default()
     };
 
-    commands
-        .spawn_bundle(NodeBundle {
-            style: node_style,
-            color: Color::NONE.into(),
+    let popup = commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(event.text.as_str(), text_style, text_alignment),
+            style,
             ..default()
         })
-        .with_children(|parent| {
-            parent
-                .spawn_bundle(TextBundle {
-                    text: Text::with_section(text, text_style, text_alignment),
-                    style,
-                    ..default()
-                })
-                .insert(Name::new("TextPopup"))
-                .insert(TextPopup {
-                    timer: Timer::from_seconds(duration, false),
-                    when_start_fading: 0.3,
-                });
-        });
+        .insert(Name::new("TextPopup"))
+        .insert(TextPopup {
+            position: event.position,
+            priority: event.priority,
+            sticky: event.sticky,
+            timer: Timer::from_seconds(event.duration, false),
+            when_start_fading: 0.3,
+        })
+        .id();
+    commands.entity(slot).add_child(popup);
 }
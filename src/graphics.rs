@@ -1,4 +1,4 @@
-use crate::combat::EnemyType;
+use crate::pause_menu::not_paused;
 use crate::player::Player;
 use crate::TILE_SIZE;
 use bevy::prelude::*;
@@ -12,8 +12,6 @@ pub struct CharacterSheet {
     pub player_down: [usize; 3],
     pub player_left: [usize; 3],
     pub player_right: [usize; 3],
-    pub bat_frames: [usize; 3],
-    pub ghost_frames: [usize; 3],
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Inspectable)]
@@ -41,7 +39,7 @@ impl Plugin for GraphicsPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system_to_stage(StartupStage::PreStartup, Self::load_graphics)
             .add_system(Self::update_player_graphics)
-            .add_system(Self::frame_animation);
+            .add_system(Self::frame_animation.with_run_criteria(not_paused));
     }
 }
 
@@ -49,22 +47,14 @@ pub fn spawn_enemy_sprite(
     commands: &mut Commands,
     characters: &CharacterSheet,
     translation: Vec3,
-    enemy_type: EnemyType,
+    frames: &[usize],
 ) -> Entity {
-    let mut sprite = match enemy_type {
-        EnemyType::Bat => TextureAtlasSprite::new(characters.bat_frames[0]),
-        EnemyType::Ghost => TextureAtlasSprite::new(characters.ghost_frames[0]),
-    };
+    let mut sprite = TextureAtlasSprite::new(frames[0]);
     sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
 
-    let frames = match enemy_type {
-        EnemyType::Bat => characters.bat_frames.to_vec(),
-        EnemyType::Ghost => characters.ghost_frames.to_vec(),
-    };
-
     let animation = FrameAnimation {
         timer: Timer::from_seconds(0.2, true),
-        frames,
+        frames: frames.to_vec(),
         current_frame: 0,
     };
 
@@ -107,8 +97,6 @@ impl GraphicsPlugin {
             player_left: [columns + 3, columns + 4, columns + 5],
             player_right: [columns * 2 + 3, columns * 2 + 4, columns * 2 + 5],
             player_up: [columns * 3 + 3, columns * 3 + 4, columns * 3 + 5],
-            bat_frames: [columns * 4 + 3, columns * 4 + 4, columns * 4 + 5],
-            ghost_frames: [columns * 4 + 6, columns * 4 + 7, columns * 4 + 8],
         });
     }
 
@@ -0,0 +1,602 @@
+use crate::ascii::{AsciiSheet, NineSliceIndices};
+use crate::combat::{
+    spawn_combat_list_menu, CombatListMenu, CombatState, CombatStats, PendingEncounter,
+};
+use crate::data::RonAssetLoader;
+use crate::equipment::{EquipSlot, Equipment, StatBonus};
+use crate::fadeout::{create_fadeout, create_warp_fadeout};
+use crate::game_ui::{CreateTextPopupEvent, TextPopupPosition, UiAssets};
+use crate::player::Player;
+use crate::tilemap::WarpTile;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+pub struct InventoryPlugin;
+
+/// Most different items the player can carry
+const MAX_INVENTORY_LINES: usize = 10;
+/// Lines the inventory screen shows at once, it scrolls for the rest
+const INVENTORY_SCREEN_LINES: usize = 8;
+/// Most items of one kind the player can carry
+const MAX_STACK: usize = 99;
+
+/// Item definitions keyed by id, loaded from `assets/data/items.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "5c1e3f0a-9d2b-4a57-8e61-2f7c0b9d4e13"]
+pub struct ItemDatabase {
+    items: HashMap<String, ItemDefinition>,
+}
+
+#[derive(Deserialize)]
+pub struct ItemDefinition {
+    pub(crate) name: String,
+    description: String,
+    pub(crate) effect: ItemEffect,
+    /// Gold a shop asks for it, shops buy it back for half and won't take it at 0
+    #[serde(default)]
+    pub(crate) price: usize,
+}
+
+#[derive(Deserialize)]
+pub enum ItemEffect {
+    /// Restores up to this much health
+    Heal(isize),
+    /// Restores up to this much MP
+    RestoreMp(isize),
+    /// Flees from combat, or warps to the given spot on the overworld
+    Escape(WarpTile),
+    /// Goes into an equipment slot instead of being used up
+    Equip(EquipSlot, StatBonus),
+}
+
+pub struct ItemAssets {
+    pub(crate) database: Handle<ItemDatabase>,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, Default)]
+pub struct Inventory {
+    /// Kept in the order the items were first picked up
+    items: Vec<ItemStack>,
+}
+
+/// Gold the player carries
+#[derive(Component, Serialize, Deserialize, Clone, Default)]
+pub struct Wallet {
+    pub(crate) gold: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ItemStack {
+    pub(crate) item: String,
+    pub(crate) count: usize,
+}
+
+pub struct UseItemEvent {
+    pub(crate) user: Entity,
+    pub(crate) item: String,
+}
+
+/// Highlighted line of the inventory screen and the first line shown
+#[derive(Default)]
+pub struct ItemSelection {
+    index: usize,
+    scroll: usize,
+}
+
+#[derive(Component)]
+pub struct InventoryScreen;
+
+#[derive(Component)]
+pub struct InventoryLine(usize);
+
+#[derive(Component)]
+pub struct InventoryDescription;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ItemDatabase>()
+            .add_asset_loader(RonAssetLoader::<ItemDatabase>::new(&["items.ron"]))
+            .add_startup_system(load_item_database)
+            .init_resource::<ItemSelection>()
+            .add_event::<UseItemEvent>()
+            .add_system(use_items)
+            .add_system_set(
+                SystemSet::on_enter(CombatState::PlayerItem).with_system(spawn_combat_item_menu),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::PlayerItem).with_system(combat_item_input),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(toggle_inventory_screen)
+                    .with_system(inventory_screen_input.after(toggle_inventory_screen))
+                    .with_system(update_inventory_screen.after(inventory_screen_input)),
+            );
+    }
+}
+
+impl Inventory {
+    pub fn starting() -> Self {
+        let mut inventory = Inventory::default();
+        inventory.add("potion", 3);
+        inventory.add("ether", 1);
+        inventory.add("escape_rope", 1);
+        inventory.add("leather_armor", 1);
+        inventory
+    }
+
+    pub fn items(&self) -> &[ItemStack] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Puts as many of the items in as fit, returns how many were left over
+    pub fn add(&mut self, item: &str, count: usize) -> usize {
+        let lines = self.items.len();
+        match self.items.iter_mut().find(|stack| stack.item == item) {
+            Some(stack) => {
+                let added = count.min(MAX_STACK - stack.count);
+                stack.count += added;
+                count - added
+            }
+            None if count == 0 => 0,
+            None if lines >= MAX_INVENTORY_LINES => count,
+            None => {
+                let added = count.min(MAX_STACK);
+                self.items.push(ItemStack {
+                    item: item.to_string(),
+                    count: added,
+                });
+                count - added
+            }
+        }
+    }
+
+    /// Whether one more of the item still fits, either on its stack or as a new line
+    pub fn can_carry(&self, item: &str) -> bool {
+        match self.items.iter().find(|stack| stack.item == item) {
+            Some(stack) => stack.count < MAX_STACK,
+            None => self.items.len() < MAX_INVENTORY_LINES,
+        }
+    }
+
+    pub fn count(&self, item: &str) -> usize {
+        self.items
+            .iter()
+            .find(|stack| stack.item == item)
+            .map_or(0, |stack| stack.count)
+    }
+
+    /// Takes one item out, returns false if there was none
+    pub fn remove_one(&mut self, item: &str) -> bool {
+        if let Some(index) = self.items.iter().position(|stack| stack.item == item) {
+            self.items[index].count -= 1;
+            if self.items[index].count == 0 {
+                self.items.remove(index);
+            }
+            return true;
+        }
+
+        false
+    }
+}
+
+impl ItemDatabase {
+    pub fn get(&self, item: &str) -> Option<&ItemDefinition> {
+        self.items.get(item)
+    }
+
+    /// Stacks in the inventory that can be used up in a fight
+    pub fn combat_items<'a>(&self, inventory: &'a Inventory) -> Vec<&'a ItemStack> {
+        inventory
+            .items()
+            .iter()
+            .filter(|stack| {
+                self.get(&stack.item)
+                    .is_some_and(|definition| !matches!(definition.effect, ItemEffect::Equip(..)))
+            })
+            .collect()
+    }
+}
+
+fn load_item_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(ItemAssets {
+        database: assets.load("data/items.ron"),
+    });
+}
+
+/// Name of an item for menus, the raw id if the database doesn't know it
+pub fn item_name(database: Option<&ItemDatabase>, item: &str) -> String {
+    database
+        .and_then(|database| database.get(item))
+        .map_or_else(|| item.to_string(), |definition| definition.name.clone())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn use_items(
+    mut commands: Commands,
+    mut ev_use_item: EventReader<UseItemEvent>,
+    mut user_query: Query<(
+        &mut Inventory,
+        &mut CombatStats,
+        Option<&mut Player>,
+        Option<&mut Equipment>,
+    )>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+    screen_query: Query<Entity, With<InventoryScreen>>,
+    encounter: Option<Res<PendingEncounter>>,
+    game_state: Res<State<GameState>>,
+    mut combat_state: ResMut<State<CombatState>>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+    ascii: Res<AsciiSheet>,
+) {
+    for event in ev_use_item.iter() {
+        let definition = match item_databases
+            .get(&item_assets.database)
+            .and_then(|database| database.get(&event.item))
+        {
+            Some(definition) => definition,
+            None => {
+                error!("Tried to use unknown item '{}'", event.item);
+                continue;
+            }
+        };
+
+        let in_combat = game_state.current() == &GameState::Combat;
+        let escape_blocked = encounter
+            .as_ref()
+            .is_some_and(|encounter| encounter.table.unescapable);
+        if in_combat && escape_blocked && matches!(definition.effect, ItemEffect::Escape(_)) {
+            ev_text_popup.send(CreateTextPopupEvent {
+                text: "There's no running from this fight!".to_string(),
+                position: TextPopupPosition::Center,
+                duration: 2.0,
+                ..default()
+            });
+            continue;
+        }
+
+        let (mut inventory, mut stats, player, equipment) = user_query
+            .get_mut(event.user)
+            .expect("Item user without an inventory!");
+        // Kept rather than used up for nothing
+        let wasted = match definition.effect {
+            ItemEffect::Heal(_) => stats.health >= stats.max_health,
+            ItemEffect::RestoreMp(_) => stats.mp >= stats.max_mp,
+            _ => false,
+        };
+        if wasted {
+            ev_text_popup.send(CreateTextPopupEvent {
+                text: format!("{} would do nothing right now.", definition.name),
+                position: TextPopupPosition::Center,
+                duration: 2.0,
+                ..default()
+            });
+            continue;
+        }
+        if !inventory.remove_one(&event.item) {
+            continue;
+        }
+
+        let text = match &definition.effect {
+            ItemEffect::Heal(amount) => {
+                // The health bars follow CombatStats on their own
+                let healed = (*amount).min(stats.max_health - stats.health);
+                stats.health += healed;
+                if in_combat {
+                    combat_state.set(CombatState::NextTurn).unwrap();
+                }
+                format!("Used {}, recovered {} HP.", definition.name, healed)
+            }
+            ItemEffect::RestoreMp(amount) => {
+                let restored = (*amount).min(stats.max_mp - stats.mp);
+                stats.mp += restored;
+                if in_combat {
+                    combat_state.set(CombatState::NextTurn).unwrap();
+                }
+                format!("Used {}, recovered {} MP.", definition.name, restored)
+            }
+            ItemEffect::Escape(warp) => {
+                if in_combat {
+                    combat_state.set(CombatState::Escaping).unwrap();
+                    create_fadeout(&mut commands, None, &ascii);
+                } else {
+                    // The player stays frozen until the map on the other side is spawned
+                    for screen in screen_query.iter() {
+                        commands.entity(screen).despawn_recursive();
+                    }
+                    if let Some(mut player) = player {
+                        player.active = false;
+                    }
+                    create_warp_fadeout(&mut commands, warp.clone(), &ascii);
+                }
+                format!("Used {}!", definition.name)
+            }
+            ItemEffect::Equip(slot, _) => {
+                let mut equipment = equipment.expect("Item user can't wear equipment!");
+                match equipment.wear(*slot, Some(event.item.clone()), &mut inventory) {
+                    Ok(()) => format!("Equipped {}.", definition.name),
+                    Err(previous) => format!(
+                        "No room in the inventory for {}.",
+                        item_name(item_databases.get(&item_assets.database), &previous)
+                    ),
+                }
+            }
+        };
+
+        ev_text_popup.send(CreateTextPopupEvent {
+            text,
+            position: TextPopupPosition::Center,
+            duration: 2.0,
+            ..default()
+        });
+    }
+}
+
+fn spawn_combat_item_menu(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    nine_slice_indices: Res<NineSliceIndices>,
+    player_query: Query<&Inventory, With<Player>>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+) {
+    let inventory = player_query.single();
+    let database = item_databases
+        .get(&item_assets.database)
+        .expect("Item database is not loaded!");
+
+    let entries = database
+        .combat_items(inventory)
+        .iter()
+        .map(|stack| {
+            let line = format!(
+                "{} x{}",
+                item_name(Some(database), &stack.item),
+                stack.count
+            );
+            (stack.item.clone(), line)
+        })
+        .collect();
+    spawn_combat_list_menu(
+        &mut commands,
+        &ascii,
+        &nine_slice_indices,
+        "ItemMenu",
+        None,
+        entries,
+    );
+}
+
+fn combat_item_input(
+    keyboard: Res<Input<KeyCode>>,
+    player_query: Query<Entity, With<Player>>,
+    menu_query: Query<&CombatListMenu>,
+    mut ev_use_item: EventWriter<UseItemEvent>,
+) {
+    if let (true, Some(item)) = (
+        keyboard.just_pressed(KeyCode::E),
+        menu_query
+            .get_single()
+            .ok()
+            .and_then(CombatListMenu::selected),
+    ) {
+        ev_use_item.send(UseItemEvent {
+            user: player_query.single(),
+            item: item.clone(),
+        });
+    }
+}
+
+fn move_selection(keyboard: &Input<KeyCode>, selection: &mut ItemSelection, count: usize) {
+    let count = count as isize;
+    let mut new_index = (selection.index as isize).min(count - 1);
+    if keyboard.just_pressed(KeyCode::W) {
+        new_index -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::S) {
+        new_index += 1;
+    }
+    selection.index = ((new_index + count) % count) as usize;
+}
+
+fn toggle_inventory_screen(
+    mut commands: Commands,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut player_query: Query<&mut Player>,
+    screen_query: Query<Entity, With<InventoryScreen>>,
+    mut selection: ResMut<ItemSelection>,
+    ui_assets: Res<UiAssets>,
+) {
+    if !keyboard.clear_just_pressed(KeyCode::I) {
+        return;
+    }
+
+    let mut player = player_query.single_mut();
+    if let Ok(screen) = screen_query.get_single() {
+        commands.entity(screen).despawn_recursive();
+        player.active = true;
+    } else if player.active {
+        player.active = false;
+        *selection = ItemSelection::default();
+        spawn_inventory_screen(&mut commands, &ui_assets);
+    }
+}
+
+fn spawn_inventory_screen(commands: &mut Commands, ui_assets: &UiAssets) {
+    let text_style = TextStyle {
+        font: ui_assets.font.clone(),
+        font_size: 26.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Percent(2.0),
+                    top: Val::Percent(4.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(35.0), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Percent(1.0)),
+                ..default()
+            },
+            color: Color::rgba(0.05, 0.05, 0.2, 0.9).into(),
+            ..default()
+        })
+        .insert(Name::new("InventoryScreen"))
+        .insert(InventoryScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Inventory",
+                    TextStyle {
+                        font: ui_assets.font_bold.clone(),
+                        color: Color::GOLD,
+                        ..text_style.clone()
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+
+            // The lines are filled in by update_inventory_screen
+            for i in 0..INVENTORY_SCREEN_LINES {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section("", text_style.clone(), default()),
+                        ..default()
+                    })
+                    .insert(InventoryLine(i));
+            }
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font_size: 20.0,
+                            color: Color::rgb(0.7, 0.7, 0.7),
+                            ..text_style.clone()
+                        },
+                        default(),
+                    ),
+                    ..default()
+                })
+                .insert(InventoryDescription);
+        });
+}
+
+fn inventory_screen_input(
+    keyboard: Res<Input<KeyCode>>,
+    player_query: Query<(Entity, &Inventory), With<Player>>,
+    screen_query: Query<&InventoryScreen>,
+    mut selection: ResMut<ItemSelection>,
+    mut ev_use_item: EventWriter<UseItemEvent>,
+) {
+    let (player, inventory) = player_query.single();
+    if screen_query.is_empty() || inventory.is_empty() {
+        return;
+    }
+
+    move_selection(&keyboard, &mut selection, inventory.items().len());
+
+    if keyboard.just_pressed(KeyCode::E) {
+        ev_use_item.send(UseItemEvent {
+            user: player,
+            item: inventory.items()[selection.index].item.clone(),
+        });
+    }
+}
+
+fn update_inventory_screen(
+    player_query: Query<(&Inventory, &CombatStats), With<Player>>,
+    screen_query: Query<&InventoryScreen>,
+    mut line_query: Query<(&mut Text, &InventoryLine), Without<InventoryDescription>>,
+    mut description_query: Query<&mut Text, With<InventoryDescription>>,
+    mut selection: ResMut<ItemSelection>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+) {
+    if screen_query.is_empty() {
+        return;
+    }
+
+    let (inventory, stats) = player_query.single();
+    let database = item_databases.get(&item_assets.database);
+    let selected = selection
+        .index
+        .min(inventory.items().len().saturating_sub(1));
+    // Scrolls only as far as it takes to keep the selected line on screen
+    if selected < selection.scroll {
+        selection.scroll = selected;
+    } else if selected >= selection.scroll + INVENTORY_SCREEN_LINES {
+        selection.scroll = selected + 1 - INVENTORY_SCREEN_LINES;
+    }
+    for (mut text, line) in line_query.iter_mut() {
+        let section = &mut text.sections[0];
+        let index = selection.scroll + line.0;
+        match inventory.items().get(index) {
+            Some(stack) => {
+                let cursor = if index == selected { "> " } else { "  " };
+                section.value = format!(
+                    "{}{} x{}",
+                    cursor,
+                    item_name(database, &stack.item),
+                    stack.count
+                );
+                section.style.color = if index == selected {
+                    Color::GOLD
+                } else {
+                    Color::rgb(0.9, 0.9, 0.9)
+                };
+            }
+            None if index == 0 => {
+                section.value = "  (empty)".to_string();
+                section.style.color = Color::rgb(0.6, 0.6, 0.6);
+            }
+            None => section.value.clear(),
+        }
+    }
+
+    let mut description = description_query.single_mut();
+    description.sections[0].value = match inventory.items().get(selected) {
+        Some(stack) => database
+            .and_then(|database| database.get(&stack.item))
+            .map_or_else(String::new, |definition| definition.description.clone()),
+        None => String::new(),
+    } + &format!(
+        "\nHP {}/{}  MP {}/{}",
+        stats.health, stats.max_health, stats.mp, stats.max_mp
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_stacks_and_lines_report_the_leftovers() {
+        let mut inventory = Inventory::default();
+        assert_eq!(inventory.add("potion", MAX_STACK + 5), 5);
+        assert_eq!(inventory.count("potion"), MAX_STACK);
+        assert_eq!(inventory.add("potion", 1), 1);
+
+        for i in 1..MAX_INVENTORY_LINES {
+            assert_eq!(inventory.add(&format!("item_{}", i), 1), 0);
+        }
+        assert!(!inventory.can_carry("ether"));
+        assert_eq!(inventory.add("ether", 2), 2);
+        assert_eq!(inventory.items().len(), MAX_INVENTORY_LINES);
+    }
+}
@@ -2,13 +2,25 @@ mod ascii;
 mod audio;
 mod camera;
 mod combat;
+mod data;
 mod debug;
+mod dialogue;
+mod equipment;
 mod fadeout;
 mod game_ui;
 mod graphics;
+mod inventory;
 mod npc;
+mod party;
+mod pause_menu;
 mod player;
+mod quest;
+mod save;
+mod settings;
+mod shop;
+mod skills;
 mod start_menu;
+mod status;
 mod tilemap;
 
 use bevy::log::LogSettings;
@@ -19,37 +31,62 @@ use crate::audio::GameAudioPlugin;
 use crate::camera::CameraPlugin;
 use crate::combat::CombatPlugin;
 use crate::debug::DebugPlugin;
+use crate::dialogue::DialoguePlugin;
+use crate::equipment::EquipmentPlugin;
 use crate::fadeout::FadeoutPlugin;
 use crate::game_ui::GameUiPlugin;
 use crate::graphics::GraphicsPlugin;
+use crate::inventory::InventoryPlugin;
 use crate::npc::NpcPlugin;
+use crate::party::PartyPlugin;
+use crate::pause_menu::PauseMenuPlugin;
 use crate::player::PlayerPlugin;
+use crate::quest::QuestPlugin;
+use crate::save::SavePlugin;
+use crate::settings::{read_settings, Settings, SettingsPlugin};
+use crate::shop::ShopPlugin;
+use crate::skills::SkillPlugin;
 use crate::start_menu::MainMenuPlugin;
+use crate::status::StatusPlugin;
 use crate::tilemap::TileMapPlugin;
 
 pub const CLEAR: Color = Color::rgb(0.1, 0.1, 0.1);
 pub const RESOLUTION: f32 = 16.0 / 9.0;
 pub const TILE_SIZE: f32 = 0.1;
+/// Window height before the window size setting scales it
+pub const WINDOW_HEIGHT: f32 = 650.0;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub enum GameState {
     StartMenu,
     Overworld,
     Combat,
+    /// Pushed over the overworld, which freezes until it's popped again
+    Paused,
+    /// Pushed over the pause menu
+    Settings,
 }
 
 fn main() {
-    let height = 650.0;
+    // Logging isn't up yet, and a broken config is replaced the next time a setting changes
+    let settings = read_settings().unwrap_or_else(|err| {
+        eprintln!("Couldn't load the settings: {:#}", err);
+        Settings::default()
+    });
+    let (width, height) = settings.window_size();
+
     App::new()
         .add_state(GameState::StartMenu)
         .insert_resource(ClearColor(CLEAR))
         .insert_resource(WindowDescriptor {
-            width: height * RESOLUTION,
+            width,
             height,
             title: "Game".to_string(),
             resizable: false,
+            mode: settings.window_mode(),
             ..default()
         })
+        .insert_resource(settings)
         .insert_resource(LogSettings {
             filter: "wgpu=error,symphonia=warn".to_string(),
             ..default()
@@ -64,8 +101,19 @@ fn main() {
         .add_plugin(GameAudioPlugin)
         .add_plugin(GraphicsPlugin)
         .add_plugin(MainMenuPlugin)
+        .add_plugin(PauseMenuPlugin)
         .add_plugin(NpcPlugin)
         .add_plugin(CameraPlugin)
         .add_plugin(GameUiPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(InventoryPlugin)
+        .add_plugin(EquipmentPlugin)
+        .add_plugin(SkillPlugin)
+        .add_plugin(StatusPlugin)
+        .add_plugin(PartyPlugin)
+        .add_plugin(ShopPlugin)
+        .add_plugin(DialoguePlugin)
+        .add_plugin(QuestPlugin)
+        .add_plugin(SettingsPlugin)
         .run();
 }
@@ -1,19 +1,23 @@
-use crate::combat::CombatStats;
-use crate::game_ui::{CreateTextPopupEvent, HealthBar, TextPopupPosition};
+use crate::dialogue::StartDialogueEvent;
+use crate::party::RecruitEvent;
 use crate::player::Player;
+use crate::shop::OpenShopEvent;
 use crate::{GameState, TILE_SIZE};
 use bevy::prelude::*;
+use serde::Deserialize;
 
 pub struct NpcPlugin;
 
-#[derive(Component)]
+#[derive(Component, Deserialize, Clone)]
 pub enum Npc {
-    Healer,
+    /// Starts the dialogue with this id
+    Talk(String),
+    /// Joins the party as the member with this id
+    Recruit(String),
+    /// Opens the shop with this id
+    Shopkeeper(String),
 }
 
-#[derive(Component)]
-pub struct NpcText;
-
 impl Plugin for NpcPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_update(GameState::Overworld).with_system(npc_speech));
@@ -21,42 +25,35 @@ impl Plugin for NpcPlugin {
 }
 
 fn npc_speech(
-    mut player_query: Query<(Entity, &Player, &mut CombatStats, &Transform), Without<HealthBar>>,
-    npc_query: Query<&Transform, (With<Npc>, Without<HealthBar>)>,
+    player_query: Query<(&Player, &Transform)>,
+    npc_query: Query<(Entity, &Npc, &Transform), Without<Player>>,
     keyboard: Res<Input<KeyCode>>,
-    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
-    mut health_bar_query: Query<(&mut Transform, &HealthBar), Without<Player>>,
+    mut ev_start_dialogue: EventWriter<StartDialogueEvent>,
+    mut ev_recruit: EventWriter<RecruitEvent>,
+    mut ev_open_shop: EventWriter<OpenShopEvent>,
 ) {
-    let (entity, player, mut stats, transform) = player_query.single_mut();
+    let (player, transform) = player_query.single();
     if !player.active {
         return;
     }
 
     if keyboard.just_pressed(KeyCode::E) {
-        for npc_transform in npc_query.iter() {
+        for (entity, npc, npc_transform) in npc_query.iter() {
             if Vec2::distance(
                 npc_transform.translation.truncate(),
                 transform.translation.truncate(),
-            ) < TILE_SIZE * 1.5
+            ) >= TILE_SIZE * 1.5
             {
-                let text = if stats.health == stats.max_health {
-                    "You seem to be doing just fine without me!".to_string()
-                } else {
-                    stats.health = stats.max_health;
-                    for (mut transform, health_bar) in health_bar_query.iter_mut() {
-                        if entity == health_bar.entity {
-                            transform.scale = Vec3::splat(1.0);
-                        }
-                    }
-
-                    "You seem weak, let me heal you!".to_string()
-                };
+                continue;
+            }
 
-                ev_text_popup.send(CreateTextPopupEvent {
-                    text,
-                    position: TextPopupPosition::Center,
-                    duration: 3.0,
-                });
+            match npc {
+                Npc::Talk(id) => ev_start_dialogue.send(StartDialogueEvent { id: id.clone() }),
+                Npc::Recruit(id) => ev_recruit.send(RecruitEvent {
+                    id: id.clone(),
+                    npc: entity,
+                }),
+                Npc::Shopkeeper(id) => ev_open_shop.send(OpenShopEvent { id: id.clone() }),
             }
         }
     }
@@ -0,0 +1,468 @@
+use crate::ascii::AsciiSheet;
+use crate::combat::{
+    spawn_action_text, ActionText, AttackEvent, AttackStyle, CombatState, CombatStats, Enemy,
+    TurnOrder,
+};
+use crate::data::RonAssetLoader;
+use crate::equipment::StatBonus;
+use crate::game_ui::{
+    create_health_bar, CreateTextPopupEvent, HealthBarType, PopupPriority, TextPopupPosition,
+};
+use crate::graphics::{CharacterSheet, FacingDirection, FrameAnimation};
+use crate::npc::Npc;
+use crate::player::{gain_exp, player_movement, Player};
+use crate::save::LoadedGame;
+use crate::status::StatusEffects;
+use crate::tilemap::{MapSpawnedEvent, TileCollider};
+use crate::{GameState, TILE_SIZE};
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+pub struct PartyPlugin;
+
+/// Distance the player covers between two recorded trail points
+const TRAIL_STEP: f32 = TILE_SIZE / 4.0;
+/// Trail points between one party member and the next, a tile's worth
+const TRAIL_SPACING: usize = 4;
+
+/// Companions that can be recruited keyed by id, loaded from `assets/data/party.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "7d3b9e21-6a4f-4c08-b5d2-19e8f0a3c6b4"]
+pub struct PartyDatabase {
+    members: HashMap<String, MemberDefinition>,
+}
+
+#[derive(Deserialize)]
+pub struct MemberDefinition {
+    name: String,
+    /// Character sheet column of the first walking frame, the rows are laid out like the player's
+    column: usize,
+    stats: CombatStats,
+}
+
+pub struct PartyAssets {
+    database: Handle<PartyDatabase>,
+}
+
+/// Companion fighting next to the player, levels up from the same exp
+#[derive(Component)]
+pub struct PartyMember {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) level: usize,
+    pub(crate) exp: usize,
+    /// Place in the chain trailing the player, 0 walks right behind them
+    pub(crate) slot: usize,
+    column: usize,
+    facing: FacingDirection,
+    /// Where the member stood on the overworld before a fight moved them
+    overworld_translation: Vec3,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MemberSave {
+    pub(crate) id: String,
+    pub(crate) level: usize,
+    pub(crate) exp: usize,
+    pub(crate) stats: CombatStats,
+    #[serde(default)]
+    pub(crate) statuses: StatusEffects,
+}
+
+/// Sent by a recruitable NPC, which is replaced by the member it stood for
+pub struct RecruitEvent {
+    pub(crate) id: String,
+    pub(crate) npc: Entity,
+}
+
+/// Members from the save waiting for the party database to finish loading
+pub struct SavedParty(Vec<MemberSave>);
+
+/// Spots the player walked through, newest first
+#[derive(Default)]
+pub struct PlayerTrail {
+    points: VecDeque<Vec3>,
+}
+
+impl Plugin for PartyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<PartyDatabase>()
+            .add_asset_loader(RonAssetLoader::<PartyDatabase>::new(&["party.ron"]))
+            .add_event::<RecruitEvent>()
+            .init_resource::<PlayerTrail>()
+            .add_startup_system(load_party_database)
+            .add_system(update_member_graphics)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Overworld).with_system(queue_saved_party),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(spawn_saved_party)
+                    .with_system(recruit_members)
+                    .with_system(snap_party_to_player)
+                    .with_system(follow_player.after(player_movement)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Combat).with_system(place_party_for_combat),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::AllyTurn(false)).with_system(process_ally_turn),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Combat)
+                    .with_system(restore_party_position)
+                    .with_system(revive_party),
+            );
+    }
+}
+
+impl PartyMember {
+    pub fn level_up(&mut self, exp: usize, stats: &mut CombatStats) -> bool {
+        gain_exp(&mut self.level, &mut self.exp, exp, stats)
+    }
+
+    fn frames(&self) -> Vec<usize> {
+        // Same layout as the player: one row per facing, three walking frames each
+        let columns = 12;
+        let row = match self.facing {
+            FacingDirection::Down => 0,
+            FacingDirection::Left => 1,
+            FacingDirection::Right => 2,
+            FacingDirection::Up => 3,
+        };
+        (0..3).map(|i| row * columns + self.column + i).collect()
+    }
+
+    /// Spot next to the player in a fight, alternating between their left and right
+    fn combat_translation(&self) -> Vec3 {
+        let side = if self.slot.is_multiple_of(2) {
+            -1.0
+        } else {
+            1.0
+        };
+        let distance = 0.6 * (self.slot / 2 + 1) as f32;
+        Vec3::new(side * distance, -0.5, 100.0)
+    }
+}
+
+fn load_party_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(PartyAssets {
+        database: assets.load("data/party.ron"),
+    });
+}
+
+fn spawn_member(
+    commands: &mut Commands,
+    characters: &CharacterSheet,
+    definition: &MemberDefinition,
+    save: MemberSave,
+    slot: usize,
+    position: Vec2,
+) {
+    let member = PartyMember {
+        id: save.id,
+        name: definition.name.clone(),
+        level: save.level,
+        exp: save.exp,
+        slot,
+        column: definition.column,
+        facing: FacingDirection::Down,
+        overworld_translation: Vec3::ZERO,
+    };
+    let frames = member.frames();
+
+    let entity = commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                index: frames[0],
+                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                ..default()
+            },
+            transform: Transform {
+                translation: position.extend(890.0 - slot as f32),
+                scale: Vec3::new(1.5, 1.5, 1.0),
+                ..default()
+            },
+            texture_atlas: characters.handle.clone(),
+            ..default()
+        })
+        .insert(FrameAnimation {
+            timer: Timer::from_seconds(0.2, true),
+            frames,
+            current_frame: 0,
+        })
+        .insert(Name::new(format!("PartyMember {}", member.name)))
+        .insert(member)
+        .insert(save.stats)
+        .insert(StatBonus::default())
+        .insert(save.statuses)
+        .id();
+
+    let health_bar_bg = create_health_bar(commands, HealthBarType::Player, entity);
+    commands.entity(entity).add_child(health_bar_bg);
+}
+
+fn queue_saved_party(mut commands: Commands, loaded_game: Res<LoadedGame>) {
+    match &loaded_game.0 {
+        Some(save) => commands.insert_resource(SavedParty(save.party.clone())),
+        None => commands.remove_resource::<SavedParty>(),
+    }
+}
+
+/// Continuing can be quicker than the party database loads, so the members wait for it
+fn spawn_saved_party(
+    mut commands: Commands,
+    characters: Res<CharacterSheet>,
+    saved_party: Option<Res<SavedParty>>,
+    player_query: Query<&Transform, With<Player>>,
+    party_assets: Res<PartyAssets>,
+    party_databases: Res<Assets<PartyDatabase>>,
+    assets: Res<AssetServer>,
+) {
+    let (saved_party, database) = match (saved_party, party_databases.get(&party_assets.database)) {
+        (Some(saved_party), Some(database)) => (saved_party, database),
+        // The loader logged why, the map waits for the party so it can't be kept waiting forever
+        (Some(_), None) if assets.get_load_state(&party_assets.database) == LoadState::Failed => {
+            commands.remove_resource::<SavedParty>();
+            return;
+        }
+        _ => return,
+    };
+    // Put in line behind the player, a map spawned later snaps them over as well
+    let position = player_query
+        .get_single()
+        .map_or(Vec2::ZERO, |transform| transform.translation.truncate());
+
+    for (slot, member) in saved_party.0.iter().enumerate() {
+        match database.members.get(&member.id) {
+            Some(definition) => spawn_member(
+                &mut commands,
+                &characters,
+                definition,
+                member.clone(),
+                slot,
+                position,
+            ),
+            None => error!("Saved party member '{}' doesn't exist", member.id),
+        }
+    }
+    commands.remove_resource::<SavedParty>();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn recruit_members(
+    mut commands: Commands,
+    mut ev_recruit: EventReader<RecruitEvent>,
+    characters: Res<CharacterSheet>,
+    member_query: Query<&PartyMember>,
+    mut npc_query: Query<(&mut TextureAtlasSprite, &Transform), With<Npc>>,
+    party_assets: Res<PartyAssets>,
+    party_databases: Res<Assets<PartyDatabase>>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    let database = match party_databases.get(&party_assets.database) {
+        Some(database) => database,
+        None => return,
+    };
+
+    let mut slot = member_query.iter().count();
+    for event in ev_recruit.iter() {
+        let definition = match database.members.get(&event.id) {
+            Some(definition) => definition,
+            None => {
+                error!("Can't recruit unknown party member '{}'", event.id);
+                continue;
+            }
+        };
+        if member_query.iter().any(|member| member.id == event.id) {
+            continue;
+        }
+
+        let save = MemberSave {
+            id: event.id.clone(),
+            level: 1,
+            exp: 0,
+            stats: definition.stats.clone(),
+            statuses: StatusEffects::default(),
+        };
+        // The NPC walks off with the player, leaving an empty floor tile
+        let mut position = Vec2::ZERO;
+        if let Ok((mut sprite, transform)) = npc_query.get_mut(event.npc) {
+            sprite.index = ' ' as usize;
+            position = transform.translation.truncate();
+        }
+        spawn_member(&mut commands, &characters, definition, save, slot, position);
+        slot += 1;
+        commands
+            .entity(event.npc)
+            .remove::<Npc>()
+            .remove::<TileCollider>();
+
+        ev_text_popup.send(CreateTextPopupEvent {
+            text: format!("{} joins the party!", definition.name),
+            position: TextPopupPosition::Center,
+            duration: 2.5,
+            priority: PopupPriority::High,
+            ..default()
+        });
+    }
+}
+
+fn update_member_graphics(
+    mut member_query: Query<(&PartyMember, &mut FrameAnimation), Changed<PartyMember>>,
+) {
+    for (member, mut animation) in member_query.iter_mut() {
+        let frames = member.frames();
+        if animation.frames != frames {
+            animation.frames = frames;
+        }
+    }
+}
+
+/// Starts the trail over after a warp so nobody walks across the map to catch up
+fn snap_party_to_player(
+    mut ev_map_spawned: EventReader<MapSpawnedEvent>,
+    mut trail: ResMut<PlayerTrail>,
+    player_query: Query<&Transform, With<Player>>,
+    mut member_query: Query<&mut Transform, (With<PartyMember>, Without<Player>)>,
+) {
+    if ev_map_spawned.iter().count() == 0 {
+        return;
+    }
+
+    trail.points.clear();
+    let player_translation = player_query.single().translation;
+    for mut transform in member_query.iter_mut() {
+        transform.translation.x = player_translation.x;
+        transform.translation.y = player_translation.y;
+    }
+}
+
+/// Each member walks a tile behind the one in front of them, along the player's path
+fn follow_player(
+    mut trail: ResMut<PlayerTrail>,
+    player_query: Query<(&Player, &Transform)>,
+    mut member_query: Query<(&mut PartyMember, &mut Transform), Without<Player>>,
+) {
+    let (player, player_transform) = player_query.single();
+    if !player.just_moved {
+        return;
+    }
+
+    let position = player_transform.translation;
+    let far_enough = trail
+        .points
+        .front()
+        .is_none_or(|last| last.truncate().distance(position.truncate()) >= TRAIL_STEP);
+    if !far_enough {
+        return;
+    }
+
+    let slots = member_query.iter().count();
+    trail.points.push_front(position);
+    trail.points.truncate((slots + 1) * TRAIL_SPACING + 1);
+
+    for (mut member, mut transform) in member_query.iter_mut() {
+        let index = (member.slot + 1) * TRAIL_SPACING;
+        let target = match trail.points.get(index) {
+            Some(target) => *target,
+            // Not enough trail yet, stay put until the player got far enough
+            None => continue,
+        };
+
+        let delta = target - transform.translation;
+        let facing = if delta.x.abs() > delta.y.abs() {
+            if delta.x > 0.0 {
+                FacingDirection::Right
+            } else {
+                FacingDirection::Left
+            }
+        } else if delta.y > 0.0 {
+            FacingDirection::Up
+        } else {
+            FacingDirection::Down
+        };
+        if delta.length() > f32::EPSILON && member.facing != facing {
+            member.facing = facing;
+        }
+
+        transform.translation.x = target.x;
+        transform.translation.y = target.y;
+    }
+}
+
+fn place_party_for_combat(mut member_query: Query<(&mut PartyMember, &mut Transform)>) {
+    for (mut member, mut transform) in member_query.iter_mut() {
+        member.overworld_translation = transform.translation;
+        member.facing = FacingDirection::Up;
+        transform.translation = member.combat_translation();
+        transform.scale = Vec3::new(5.0, 5.0, 1.0);
+    }
+}
+
+fn restore_party_position(mut member_query: Query<(&mut PartyMember, &mut Transform)>) {
+    for (mut member, mut transform) in member_query.iter_mut() {
+        transform.translation = member.overworld_translation;
+        transform.scale = Vec3::new(1.5, 1.5, 1.0);
+        member.facing = FacingDirection::Down;
+    }
+}
+
+/// Members knocked out in a won fight get back up with a single hit point
+#[allow(clippy::type_complexity)]
+fn revive_party(mut party_query: Query<&mut CombatStats, Or<(With<Player>, With<PartyMember>)>>) {
+    for mut stats in party_query.iter_mut() {
+        if stats.health <= 0 {
+            stats.health = 1;
+        }
+    }
+}
+
+/// Members pick their own fights: the weakest enemy still standing
+#[allow(clippy::too_many_arguments)]
+fn process_ally_turn(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    mut attack_event: EventWriter<AttackEvent>,
+    mut combat_state: ResMut<State<CombatState>>,
+    turn_order: Res<TurnOrder>,
+    member_query: Query<(&PartyMember, &CombatStats, Option<&StatBonus>)>,
+    enemy_query: Query<(Entity, &CombatStats), With<Enemy>>,
+    text_query: Query<Entity, With<ActionText>>,
+) {
+    let (member, stats, bonus) = turn_order
+        .current()
+        .and_then(|member| member_query.get(member).ok())
+        .expect("Ally turn without a party member!");
+
+    let target = enemy_query
+        .iter()
+        .filter(|(_, stats)| stats.health > 0)
+        .min_by_key(|(_, stats)| stats.health)
+        .map(|(enemy, _)| enemy);
+    let target = match target {
+        Some(target) => target,
+        None => {
+            combat_state.set(CombatState::NextTurn).unwrap();
+            return;
+        }
+    };
+
+    attack_event.send(AttackEvent {
+        next_state: CombatState::PlayerAttack,
+        ..stats
+            .derived(bonus)
+            .attack_event(&AttackStyle::default(), target)
+    });
+    spawn_action_text(
+        &mut commands,
+        &ascii,
+        &text_query,
+        &format!("{} attacks!", member.name),
+    );
+    combat_state.set(CombatState::AllyTurn(true)).unwrap();
+}
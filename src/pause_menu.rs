@@ -0,0 +1,231 @@
+use crate::ascii::AsciiSheet;
+use crate::fadeout::create_fadeout;
+use crate::game_ui::UiAssets;
+use crate::player::Player;
+use crate::save::SaveGameEvent;
+use crate::start_menu::spawn_menu_button;
+use crate::GameState;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+
+pub struct PauseMenuPlugin;
+
+#[derive(Component)]
+struct PauseMenu;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum PauseMenuAction {
+    Resume,
+    Settings,
+    Save,
+    Quit,
+}
+
+/// Buttons from the top down
+const PAUSE_MENU_ACTIONS: [(&str, PauseMenuAction); 4] = [
+    ("Resume", PauseMenuAction::Resume),
+    ("Settings", PauseMenuAction::Settings),
+    ("Save", PauseMenuAction::Save),
+    ("Quit to Title", PauseMenuAction::Quit),
+];
+
+/// The button the keyboard is on, only around while the menu is open
+struct PauseMenuSelection {
+    selected: usize,
+}
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Overworld).with_system(open_pause_menu))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused)
+                    .with_system(reset_pause_selection)
+                    .with_system(spawn_pause_menu),
+            )
+            // The settings menu goes on top, the pause menu comes back as it was once it closes
+            .add_system_set(SystemSet::on_pause(GameState::Paused).with_system(despawn_pause_menu))
+            .add_system_set(SystemSet::on_resume(GameState::Paused).with_system(spawn_pause_menu))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(pause_menu_input)
+                    .with_system(highlight_pause_button.after(pause_menu_input)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused)
+                    .with_system(despawn_pause_menu)
+                    .with_system(remove_pause_selection),
+            );
+    }
+}
+
+/// Run criteria for the systems that run in every state, so their timers stop under the pause
+/// menu and the settings opened from it
+pub fn not_paused(state: Res<State<GameState>>) -> ShouldRun {
+    let paused =
+        state.current() == &GameState::Paused || state.inactives().contains(&GameState::Paused);
+    if paused {
+        ShouldRun::No
+    } else {
+        ShouldRun::Yes
+    }
+}
+
+fn open_pause_menu(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    player_query: Query<&Player>,
+    mut state: ResMut<State<GameState>>,
+) {
+    // Shops, dialogue, the quest log and fadeouts all keep the player inactive, and Escape is theirs then
+    if !player_query.single().active || !keyboard.clear_just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    let _ = state.push(GameState::Paused);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_menu_input(
+    mut commands: Commands,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    selection: Option<ResMut<PauseMenuSelection>>,
+    interaction_query: Query<(&PauseMenuAction, &Interaction), Changed<Interaction>>,
+    menu_query: Query<Entity, With<PauseMenu>>,
+    mut state: ResMut<State<GameState>>,
+    ascii: Res<AsciiSheet>,
+    mut ev_save_game: EventWriter<SaveGameEvent>,
+) {
+    // Gone once Quit to Title was picked, the fadeout takes it from there
+    let mut selection = match selection {
+        Some(selection) => selection,
+        None => return,
+    };
+
+    let mut chosen = None;
+    for (action, interaction) in interaction_query.iter() {
+        let index = PAUSE_MENU_ACTIONS
+            .iter()
+            .position(|(_, other)| other == action)
+            .unwrap();
+        match interaction {
+            Interaction::Clicked => chosen = Some(*action),
+            Interaction::Hovered => selection.selected = index,
+            Interaction::None => {}
+        }
+    }
+
+    let count = PAUSE_MENU_ACTIONS.len();
+    if keyboard.just_pressed(KeyCode::W) {
+        selection.selected = (selection.selected + count - 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::S) {
+        selection.selected = (selection.selected + 1) % count;
+    }
+    if keyboard.clear_just_pressed(KeyCode::E) || keyboard.clear_just_pressed(KeyCode::Return) {
+        chosen = Some(PAUSE_MENU_ACTIONS[selection.selected].1);
+    }
+    // Cleared so the overworld doesn't take the same press as a reason to pause again
+    if keyboard.clear_just_pressed(KeyCode::Escape) {
+        chosen = Some(PauseMenuAction::Resume);
+    }
+
+    match chosen {
+        Some(PauseMenuAction::Resume) => {
+            let _ = state.pop();
+        }
+        Some(PauseMenuAction::Settings) => {
+            let _ = state.push(GameState::Settings);
+        }
+        Some(PauseMenuAction::Save) => {
+            ev_save_game.send(SaveGameEvent);
+        }
+        Some(PauseMenuAction::Quit) => {
+            for menu in menu_query.iter() {
+                commands.entity(menu).despawn_recursive();
+            }
+            commands.remove_resource::<PauseMenuSelection>();
+            create_fadeout(&mut commands, Some(GameState::StartMenu), &ascii);
+        }
+        None => {}
+    }
+}
+
+/// The selected button looks pressed, whether the keyboard or the mouse got it there
+fn highlight_pause_button(
+    selection: Option<Res<PauseMenuSelection>>,
+    button_query: Query<(&Children, &PauseMenuAction)>,
+    mut image_query: Query<&mut UiImage>,
+    ui_assets: Res<UiAssets>,
+) {
+    let selection = match selection {
+        Some(selection) => selection,
+        None => return,
+    };
+
+    // Checked every frame since the buttons are respawned when coming back from the settings
+    for (children, action) in button_query.iter() {
+        let selected = PAUSE_MENU_ACTIONS[selection.selected].1 == *action;
+        let wanted = if selected {
+            &ui_assets.button_pressed
+        } else {
+            &ui_assets.button
+        };
+        let child = children.iter().next().unwrap();
+        let mut image = image_query.get_mut(*child).unwrap();
+        if image.0 != *wanted {
+            image.0 = wanted.clone();
+        }
+    }
+}
+
+fn reset_pause_selection(mut commands: Commands) {
+    commands.insert_resource(PauseMenuSelection { selected: 0 });
+}
+
+fn remove_pause_selection(mut commands: Commands) {
+    commands.remove_resource::<PauseMenuSelection>();
+}
+
+fn spawn_pause_menu(mut commands: Commands, ui_assets: Res<UiAssets>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..default()
+        })
+        .insert(PauseMenu)
+        .insert(Name::new("Pause Menu"))
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Percent(2.0)),
+                    ..default()
+                },
+                text: Text::with_section(
+                    "Paused",
+                    TextStyle {
+                        font: ui_assets.font_bold.clone(),
+                        font_size: 50.0,
+                        color: Color::GOLD,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+            for (text, action) in PAUSE_MENU_ACTIONS {
+                spawn_menu_button(parent, &ui_assets, text, action);
+            }
+        });
+}
+
+fn despawn_pause_menu(mut commands: Commands, menu_query: Query<Entity, With<PauseMenu>>) {
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
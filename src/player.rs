@@ -1,9 +1,17 @@
 use crate::ascii::AsciiSheet;
-use crate::combat::CombatStats;
-use crate::fadeout::create_fadeout;
+use crate::combat::{CombatStats, PendingEncounter};
+use crate::equipment::{Equipment, StatBonus};
+use crate::fadeout::{create_fadeout, create_warp_fadeout};
 use crate::game_ui::{create_health_bar, HealthBarType};
 use crate::graphics::{CharacterSheet, FacingDirection, FrameAnimation, PlayerGraphics};
-use crate::tilemap::{EncounterSpawner, TileCollider};
+use crate::inventory::{Inventory, Wallet};
+use crate::pause_menu::not_paused;
+use crate::save::LoadedGame;
+use crate::skills::Skills;
+use crate::status::StatusEffects;
+use crate::tilemap::{
+    BossTile, CurrentMap, EncounterSpawner, MapDefinition, TileCollider, WarpTile,
+};
 use crate::{GameState, TILE_SIZE};
 use bevy::prelude::*;
 use bevy::sprite::collide_aabb::collide;
@@ -58,29 +66,46 @@ impl Default for Player {
 
 impl Player {
     pub fn level_up(&mut self, exp: usize, stats: &mut CombatStats) -> bool {
-        self.exp += exp;
-        let exp_needed = self.xp_required_for_current_level();
-        if self.exp >= exp_needed {
-            stats.health += 2;
-            stats.max_health += 2;
-            stats.attack += 1;
-            stats.defense += 1;
-            self.exp -= exp_needed;
-            self.level += 1;
-
-            return true;
-        }
-
-        false
+        gain_exp(&mut self.level, &mut self.exp, exp, stats)
     }
 
     pub fn xp_required_for_current_level(&self) -> usize {
-        // lvl 1 -> 2: 50 xp
-        // lvl 2 -> 3: 60 xp
-        // lvl 3 -> 4: 72 xp
-        let multiplier: f32 = 1.20;
-        (multiplier.powi((self.level - 1) as i32) * 50.0) as usize
+        xp_required_for_level(self.level)
+    }
+}
+
+/// Adds the exp and grows the stats once there's enough for the next level,
+/// party members level up by the same rules as the player
+pub fn gain_exp(
+    level: &mut usize,
+    exp: &mut usize,
+    gained: usize,
+    stats: &mut CombatStats,
+) -> bool {
+    *exp += gained;
+    let exp_needed = xp_required_for_level(*level);
+    if *exp >= exp_needed {
+        stats.health += 2;
+        stats.max_health += 2;
+        stats.attack += 1;
+        stats.defense += 1;
+        stats.mp += 1;
+        stats.max_mp += 1;
+        *exp -= exp_needed;
+        *level += 1;
+
+        return true;
     }
+
+    false
+}
+
+pub fn xp_required_for_level(level: usize) -> usize {
+    // lvl 1 -> 2: 50 xp
+    // lvl 2 -> 3: 60 xp
+    // lvl 3 -> 4: 72 xp
+    let multiplier: f32 = 1.20;
+    (multiplier.powi((level - 1) as i32) * 50.0) as usize
 }
 
 impl Plugin for PlayerPlugin {
@@ -90,17 +115,19 @@ impl Plugin for PlayerPlugin {
             facing: FacingDirection::Up,
             scale: Vec3::ONE,
         })
-        .add_system_set(SystemSet::on_resume(GameState::Overworld).with_system(restore_player_data))
+        .add_system_set(SystemSet::on_exit(GameState::Combat).with_system(restore_player_data))
         .add_system_set(
-            SystemSet::on_pause(GameState::Overworld).with_system(place_player_and_save_data),
+            SystemSet::on_enter(GameState::Combat).with_system(place_player_and_save_data),
         )
         .add_system_set(
             SystemSet::on_update(GameState::Overworld)
                 .with_system(player_movement)
-                .with_system(player_encounter_checking.after(player_movement)),
+                .with_system(player_encounter_checking.after(player_movement))
+                .with_system(player_boss_checking.after(player_movement))
+                .with_system(player_warp_checking.after(player_movement)),
         )
         .add_system_set(SystemSet::on_enter(GameState::Overworld).with_system(spawn_player))
-        .add_system(update_trauma);
+        .add_system(update_trauma.with_run_criteria(not_paused));
     }
 }
 
@@ -143,30 +170,99 @@ fn restore_player_data(
 fn player_encounter_checking(
     mut commands: Commands,
     mut player_query: Query<(&mut Player, &EncounterTracker, &Transform)>,
-    encounter_query: Query<&Transform, (With<EncounterSpawner>, Without<Player>)>,
+    encounter_query: Query<(&Transform, &EncounterSpawner), Without<Player>>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapDefinition>>,
     ascii: Res<AsciiSheet>,
     time: Res<Time>,
 ) {
     let (mut player, encounter_tracker, player_transform) = player_query.single_mut();
     let player_translation = player_transform.translation;
 
-    if player.just_moved
-        && encounter_query
-            .iter()
-            .any(|&transform| wall_collision_check(player_translation, transform.translation))
-    {
+    let spawner = encounter_query
+        .iter()
+        .find(|(transform, _)| wall_collision_check(player_translation, transform.translation));
+
+    if let (true, Some((_, spawner))) = (player.just_moved, spawner) {
         player.walked_ground_type = WalkedGroundType::Grass;
 
         let mut rng = thread_rng();
         if rng.gen::<f32>() * encounter_tracker.avg_time < time.delta_seconds() {
-            player.active = false;
-            create_fadeout(&mut commands, Some(GameState::Combat), &ascii);
+            if let Some(table) = current_map.encounter_table(&maps, &spawner.table) {
+                commands.insert_resource(PendingEncounter {
+                    table: table.clone(),
+                    boss: None,
+                    enemy_fled: false,
+                });
+                player.active = false;
+                create_fadeout(&mut commands, Some(GameState::Combat), &ascii);
+            }
         }
     } else if player.just_moved {
         player.walked_ground_type = WalkedGroundType::Normal;
     }
 }
 
+/// Boss tiles are solid, the fight starts when the player pushes against one
+fn player_boss_checking(
+    mut commands: Commands,
+    mut player_query: Query<(&mut Player, &Transform, &PlayerGraphics)>,
+    boss_query: Query<(&Transform, &BossTile), Without<Player>>,
+    keyboard: Res<Input<KeyCode>>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapDefinition>>,
+    ascii: Res<AsciiSheet>,
+) {
+    let (mut player, player_transform, graphics) = player_query.single_mut();
+    if !player.active || !keyboard.any_pressed([KeyCode::W, KeyCode::A, KeyCode::S, KeyCode::D]) {
+        return;
+    }
+
+    let facing = match graphics.facing {
+        FacingDirection::Up => Vec3::Y,
+        FacingDirection::Down => -Vec3::Y,
+        FacingDirection::Left => -Vec3::X,
+        FacingDirection::Right => Vec3::X,
+    };
+    let reach = player_transform.translation + facing * TILE_SIZE * 0.1;
+
+    if let Some((_, boss)) = boss_query
+        .iter()
+        .find(|(transform, _)| wall_collision_check(reach, transform.translation))
+    {
+        if let Some(table) = current_map.encounter_table(&maps, &boss.table) {
+            let mut table = table.clone();
+            table.unescapable = true;
+            commands.insert_resource(PendingEncounter {
+                table,
+                boss: Some(boss.id.clone()),
+                enemy_fled: false,
+            });
+            player.active = false;
+            create_fadeout(&mut commands, Some(GameState::Combat), &ascii);
+        }
+    }
+}
+
+fn player_warp_checking(
+    mut commands: Commands,
+    mut player_query: Query<(&mut Player, &Transform)>,
+    warp_query: Query<(&Transform, &WarpTile), Without<Player>>,
+    ascii: Res<AsciiSheet>,
+) {
+    let (mut player, player_transform) = player_query.single_mut();
+    if !player.just_moved {
+        return;
+    }
+
+    if let Some((_, warp)) = warp_query.iter().find(|(transform, _)| {
+        wall_collision_check(player_transform.translation, transform.translation)
+    }) {
+        player.active = false;
+        create_warp_fadeout(&mut commands, warp.clone(), &ascii);
+    }
+}
+
 pub fn player_movement(
     mut player_query: Query<(&mut Player, &mut Transform, &mut PlayerGraphics)>,
     wall_query: Query<&Transform, (With<TileCollider>, Without<Player>)>,
@@ -235,7 +331,42 @@ fn wall_collision_check(target_player_pos: Vec3, wall_translation: Vec3) -> bool
     collision.is_some()
 }
 
-fn spawn_player(mut commands: Commands, characters: Res<CharacterSheet>) {
+fn spawn_player(
+    mut commands: Commands,
+    characters: Res<CharacterSheet>,
+    loaded_game: Res<LoadedGame>,
+) {
+    let mut player = Player::default();
+    let mut inventory = Inventory::starting();
+    let mut equipment = Equipment::starting();
+    let mut wallet = Wallet::default();
+    let mut statuses = StatusEffects::default();
+    let mut stats = CombatStats {
+        health: 10,
+        max_health: 10,
+        attack: 2,
+        defense: 1,
+        speed: 3,
+        mp: 5,
+        max_mp: 5,
+        luck: 5,
+        evasion: 5,
+    };
+    // The map spawn point decides the position unless we continue from a save
+    let mut translation = Vec3::new(2.0 * TILE_SIZE, -2.0 * TILE_SIZE, 900.0);
+
+    if let Some(save) = &loaded_game.0 {
+        player.level = save.player.level;
+        player.exp = save.player.exp;
+        stats = save.player.stats.clone();
+        inventory = save.player.inventory.clone();
+        equipment = save.player.equipment.clone();
+        wallet = save.player.wallet.clone();
+        statuses = save.player.statuses.clone();
+        translation.x = save.position.0;
+        translation.y = save.position.1;
+    }
+
     let player = commands
         .spawn_bundle(SpriteSheetBundle {
             sprite: TextureAtlasSprite {
@@ -244,7 +375,7 @@ fn spawn_player(mut commands: Commands, characters: Res<CharacterSheet>) {
                 ..default()
             },
             transform: Transform {
-                translation: Vec3::new(2.0 * TILE_SIZE, -2.0 * TILE_SIZE, 900.0),
+                translation,
                 rotation: Default::default(),
                 scale: Vec3::new(1.5, 1.5, 1.0),
             },
@@ -260,13 +391,14 @@ fn spawn_player(mut commands: Commands, characters: Res<CharacterSheet>) {
             facing: FacingDirection::Down,
         })
         .insert(Name::new("Player"))
-        .insert(Player::default())
-        .insert(CombatStats {
-            health: 10,
-            max_health: 10,
-            attack: 2,
-            defense: 1,
-        })
+        .insert(player)
+        .insert(stats)
+        .insert(inventory)
+        .insert(equipment)
+        .insert(wallet)
+        .insert(StatBonus::default())
+        .insert(Skills::default())
+        .insert(statuses)
         .insert(EncounterTracker { avg_time: 1.2 })
         .id();
 
@@ -0,0 +1,486 @@
+use crate::combat::{
+    EnemyDatabase, EnemyDefeatedEvent, GiveRewardEvent, Rewards, ENEMY_DATABASE_PATH,
+};
+use crate::dialogue::{DialogueDatabase, StartDialogueEvent, StoryState, DIALOGUE_DATABASE_PATH};
+use crate::game_ui::{CreateTextPopupEvent, PopupPriority, TextPopupPosition, UiAssets};
+use crate::pause_menu::not_paused;
+use crate::player::Player;
+use crate::save::LoadedGame;
+use crate::tilemap::{map_path, CurrentMap, MapSpawnedEvent};
+use crate::GameState;
+use anyhow::{bail, Context};
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+pub struct QuestPlugin;
+
+/// Quests keyed by id, loaded from `assets/data/quests.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "e2a7c4f9-5b16-4d83-9f0e-8c3b1a6d7e42"]
+pub struct QuestDatabase {
+    quests: HashMap<String, QuestDefinition>,
+}
+
+#[derive(Deserialize)]
+pub struct QuestDefinition {
+    name: String,
+    description: String,
+    /// All of them have to be met, in any order
+    objectives: Vec<Objective>,
+    #[serde(default)]
+    reward: Rewards,
+}
+
+#[derive(Deserialize)]
+pub struct Objective {
+    /// Shown in the quest log
+    text: String,
+    goal: Goal,
+}
+
+#[derive(Deserialize)]
+pub enum Goal {
+    /// Defeat this many of the enemy with this id
+    Defeat(String, usize),
+    /// Talk to whoever starts the dialogue with this id
+    Talk(String),
+    /// Set foot on the map with this name
+    Reach(String),
+    /// Get the player to this level
+    Level(usize),
+    Flag(String),
+}
+
+pub struct QuestAssets {
+    database: Handle<QuestDatabase>,
+}
+
+/// Quests the player has taken on, kept in the save
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct QuestLog {
+    /// In the order they were started
+    active: Vec<QuestProgress>,
+    done: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QuestProgress {
+    id: String,
+    /// Counts towards each objective, empty until update_quests first looks at the quest
+    progress: Vec<usize>,
+}
+
+#[derive(Component)]
+pub struct QuestLogScreen;
+
+#[derive(Default)]
+pub struct QuestLoader;
+
+impl Plugin for QuestPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<QuestDatabase>()
+            .init_asset_loader::<QuestLoader>()
+            .init_resource::<QuestLog>()
+            .add_startup_system(load_quest_database)
+            .add_system_set(SystemSet::on_enter(GameState::Overworld).with_system(load_quest_log))
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld).with_system(toggle_quest_log_screen),
+            )
+            .add_system(update_quests.with_run_criteria(not_paused));
+    }
+}
+
+impl Goal {
+    /// How much progress completes the objective
+    fn required(&self) -> usize {
+        match self {
+            Goal::Defeat(_, count) => *count,
+            _ => 1,
+        }
+    }
+}
+
+impl QuestDatabase {
+    /// Goals naming enemies or dialogue that don't exist could never be met
+    fn validate(
+        &self,
+        enemies: &EnemyDatabase,
+        dialogues: &DialogueDatabase,
+    ) -> Result<(), anyhow::Error> {
+        for (id, quest) in self.quests.iter() {
+            if quest.objectives.is_empty() {
+                bail!("quest '{}' has no objectives", id);
+            }
+
+            for objective in quest.objectives.iter() {
+                match &objective.goal {
+                    Goal::Defeat(enemy, _) if !enemies.contains(enemy) => {
+                        bail!("quest '{}' asks to defeat unknown enemy '{}'", id, enemy)
+                    }
+                    Goal::Defeat(enemy, 0) => {
+                        bail!("quest '{}' asks to defeat 0 of '{}'", id, enemy)
+                    }
+                    Goal::Talk(dialogue) if !dialogues.contains(dialogue) => {
+                        bail!(
+                            "quest '{}' asks to talk through unknown dialogue '{}'",
+                            id,
+                            dialogue
+                        )
+                    }
+                    Goal::Level(0) => bail!("quest '{}' asks for level 0", id),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn maps(&self) -> impl Iterator<Item = &String> {
+        self.quests
+            .values()
+            .flat_map(|quest| quest.objectives.iter())
+            .filter_map(|objective| match &objective.goal {
+                Goal::Reach(map) => Some(map),
+                _ => None,
+            })
+    }
+}
+
+impl AssetLoader for QuestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let database: QuestDatabase = ron::de::from_bytes(bytes)?;
+            let path = load_context.path().display().to_string();
+            // Goals name enemies, dialogue and maps, so those are read as well to check them
+            let enemy_bytes = load_context.read_asset_bytes(ENEMY_DATABASE_PATH).await?;
+            let enemies: EnemyDatabase = ron::de::from_bytes(&enemy_bytes)
+                .with_context(|| format!("couldn't read {}", ENEMY_DATABASE_PATH))?;
+            let dialogue_bytes = load_context
+                .read_asset_bytes(DIALOGUE_DATABASE_PATH)
+                .await?;
+            let dialogues: DialogueDatabase = ron::de::from_bytes(&dialogue_bytes)
+                .with_context(|| format!("couldn't read {}", DIALOGUE_DATABASE_PATH))?;
+            database
+                .validate(&enemies, &dialogues)
+                .with_context(|| format!("invalid {}", path))?;
+            for map in database.maps() {
+                load_context
+                    .read_asset_bytes(map_path(map))
+                    .await
+                    .with_context(|| format!("invalid {}: no map '{}' to reach", path, map))?;
+            }
+            load_context.set_default_asset(LoadedAsset::new(database));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["quests.ron"]
+    }
+}
+
+impl QuestLog {
+    pub fn is_active(&self, quest: &str) -> bool {
+        self.active.iter().any(|progress| progress.id == quest)
+    }
+
+    pub fn is_done(&self, quest: &str) -> bool {
+        self.done.iter().any(|done| done == quest)
+    }
+
+    /// Takes the quest on, does nothing if it's already active or done
+    pub fn start(&mut self, quest: &str) {
+        if !self.is_active(quest) && !self.is_done(quest) {
+            self.active.push(QuestProgress {
+                id: quest.to_string(),
+                progress: Vec::new(),
+            });
+        }
+    }
+}
+
+fn load_quest_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(QuestAssets {
+        database: assets.load("data/quests.ron"),
+    });
+}
+
+fn load_quest_log(mut commands: Commands, loaded_game: Res<LoadedGame>) {
+    let quests = match &loaded_game.0 {
+        Some(save) => save.quests.clone(),
+        None => QuestLog::default(),
+    };
+    commands.insert_resource(quests);
+}
+
+/// Counts the events the objectives care about and hands out rewards for finished quests
+#[allow(clippy::too_many_arguments)]
+fn update_quests(
+    mut quests: ResMut<QuestLog>,
+    mut ev_enemy_defeated: EventReader<EnemyDefeatedEvent>,
+    mut ev_start_dialogue: EventReader<StartDialogueEvent>,
+    mut ev_map_spawned: EventReader<MapSpawnedEvent>,
+    player_query: Query<&Player>,
+    story: Res<StoryState>,
+    current_map: Option<Res<CurrentMap>>,
+    quest_assets: Res<QuestAssets>,
+    quest_databases: Res<Assets<QuestDatabase>>,
+    mut ev_give_reward: EventWriter<GiveRewardEvent>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    let defeated: Vec<String> = ev_enemy_defeated
+        .iter()
+        .map(|event| event.id.clone())
+        .collect();
+    let talked: Vec<String> = ev_start_dialogue
+        .iter()
+        .map(|event| event.id.clone())
+        .collect();
+    let map_spawned = ev_map_spawned.iter().count() > 0;
+
+    let (database, player, current_map) = match (
+        quest_databases.get(&quest_assets.database),
+        player_query.get_single(),
+        current_map,
+    ) {
+        (Some(database), Ok(player), Some(current_map)) => (database, player, current_map),
+        _ => return,
+    };
+    if quests.active.is_empty() {
+        return;
+    }
+
+    let mut finished = Vec::new();
+    for quest in quests.active.iter_mut() {
+        let definition = match database.quests.get(&quest.id) {
+            Some(definition) => definition,
+            None => {
+                error!("Quest log has unknown quest '{}'", quest.id);
+                continue;
+            }
+        };
+
+        // Reaching a map counts when the quest is taken on right there
+        let just_started = quest.progress.is_empty();
+        if just_started {
+            quest.progress = vec![0; definition.objectives.len()];
+            ev_text_popup.send(CreateTextPopupEvent {
+                text: format!("New quest: {}", definition.name),
+                position: TextPopupPosition::Left,
+                duration: 2.5,
+                priority: PopupPriority::High,
+                ..default()
+            });
+        }
+
+        for (objective, progress) in definition.objectives.iter().zip(quest.progress.iter_mut()) {
+            let required = objective.goal.required();
+            let new_progress = match &objective.goal {
+                Goal::Defeat(enemy, _) => {
+                    *progress + defeated.iter().filter(|id| *id == enemy).count()
+                }
+                Goal::Talk(dialogue) => usize::from(talked.contains(dialogue)),
+                Goal::Reach(map) => {
+                    usize::from((just_started || map_spawned) && &current_map.name == map)
+                }
+                Goal::Level(level) => usize::from(player.level >= *level),
+                Goal::Flag(flag) => usize::from(story.flags.contains(flag)),
+            };
+            // Once met an objective stays met, even if the level or flag it asked for goes away
+            *progress = new_progress.max(*progress).min(required);
+        }
+
+        let complete = definition
+            .objectives
+            .iter()
+            .zip(quest.progress.iter())
+            .all(|(objective, progress)| *progress >= objective.goal.required());
+        if complete {
+            finished.push(quest.id.clone());
+        }
+    }
+
+    for id in finished {
+        quests.active.retain(|quest| quest.id != id);
+        let definition = &database.quests[&id];
+        ev_give_reward.send(GiveRewardEvent {
+            rewards: definition.reward.clone(),
+            heading: Some(format!("Quest complete: {}", definition.name)),
+        });
+        quests.done.push(id);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn toggle_quest_log_screen(
+    mut commands: Commands,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut player_query: Query<&mut Player>,
+    screen_query: Query<Entity, With<QuestLogScreen>>,
+    quests: Res<QuestLog>,
+    quest_assets: Res<QuestAssets>,
+    quest_databases: Res<Assets<QuestDatabase>>,
+    ui_assets: Res<UiAssets>,
+) {
+    if !keyboard.clear_just_pressed(KeyCode::L) {
+        return;
+    }
+
+    let mut player = player_query.single_mut();
+    if let Ok(screen) = screen_query.get_single() {
+        commands.entity(screen).despawn_recursive();
+        player.active = true;
+    } else if player.active {
+        if let Some(database) = quest_databases.get(&quest_assets.database) {
+            player.active = false;
+            spawn_quest_log_screen(&mut commands, &ui_assets, &quests, database);
+        }
+    }
+}
+
+fn spawn_quest_log_screen(
+    commands: &mut Commands,
+    ui_assets: &UiAssets,
+    quests: &QuestLog,
+    database: &QuestDatabase,
+) {
+    let text_style = TextStyle {
+        font: ui_assets.font.clone(),
+        font_size: 26.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    let small_style = TextStyle {
+        font_size: 20.0,
+        color: Color::rgb(0.7, 0.7, 0.7),
+        ..text_style.clone()
+    };
+    let title_style = TextStyle {
+        font: ui_assets.font_bold.clone(),
+        color: Color::GOLD,
+        ..text_style.clone()
+    };
+
+    // (text, style) pairs from the top down
+    let mut lines = vec![("Quests".to_string(), title_style.clone())];
+    for quest in quests.active.iter() {
+        let definition = match database.quests.get(&quest.id) {
+            Some(definition) => definition,
+            None => continue,
+        };
+        lines.push((definition.name.clone(), text_style.clone()));
+        lines.push((definition.description.clone(), small_style.clone()));
+        for (i, objective) in definition.objectives.iter().enumerate() {
+            let progress = quest.progress.get(i).copied().unwrap_or(0);
+            let required = objective.goal.required();
+            let check = if progress >= required { "x" } else { " " };
+            let count = if required > 1 {
+                format!(" {}/{}", progress, required)
+            } else {
+                String::new()
+            };
+            lines.push((
+                format!("  [{}] {}{}", check, objective.text, count),
+                text_style.clone(),
+            ));
+        }
+    }
+    if quests.active.is_empty() {
+        lines.push((
+            "  (no quests)".to_string(),
+            TextStyle {
+                color: Color::rgb(0.6, 0.6, 0.6),
+                ..text_style.clone()
+            },
+        ));
+    }
+    if !quests.done.is_empty() {
+        lines.push(("Done".to_string(), title_style));
+        for id in quests.done.iter() {
+            if let Some(definition) = database.quests.get(id) {
+                lines.push((format!("  {}", definition.name), small_style.clone()));
+            }
+        }
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(2.0),
+                    top: Val::Percent(4.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(45.0), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Percent(1.0)),
+                ..default()
+            },
+            color: Color::rgba(0.05, 0.05, 0.2, 0.9).into(),
+            ..default()
+        })
+        .insert(Name::new("QuestLogScreen"))
+        .insert(QuestLogScreen)
+        .with_children(|parent| {
+            for (text, style) in lines {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(text, style, default()),
+                    ..default()
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shipped_data() -> (EnemyDatabase, DialogueDatabase) {
+        (
+            ron::from_str(include_str!("../assets/data/enemies.ron")).unwrap(),
+            ron::from_str(include_str!("../assets/data/dialogue.ron")).unwrap(),
+        )
+    }
+
+    #[test]
+    fn shipped_quests_are_valid() {
+        let database: QuestDatabase =
+            ron::from_str(include_str!("../assets/data/quests.ron")).unwrap();
+        let (enemies, dialogues) = shipped_data();
+        if let Err(err) = database.validate(&enemies, &dialogues) {
+            panic!("{:#}", err);
+        }
+        for map in database.maps() {
+            assert!(
+                std::path::Path::new("assets").join(map_path(map)).exists(),
+                "no map '{}'",
+                map
+            );
+        }
+    }
+
+    #[test]
+    fn quests_that_cant_be_finished_are_rejected() {
+        let (enemies, dialogues) = shipped_data();
+        for goal in [
+            r#"Defeat("bat", 0)"#,
+            r#"Defeat("bta", 5)"#,
+            r#"Talk("nobody")"#,
+        ] {
+            let database: QuestDatabase = ron::from_str(&format!(
+                r#"(quests: {{"q": (name: "Q", description: "", objectives: [(text: "", goal: {})])}})"#,
+                goal
+            ))
+            .unwrap();
+            assert!(database.validate(&enemies, &dialogues).is_err(), "{}", goal);
+        }
+    }
+}
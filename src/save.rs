@@ -0,0 +1,222 @@
+use crate::combat::CombatStats;
+use crate::dialogue::StoryState;
+use crate::equipment::Equipment;
+use crate::game_ui::{CreateTextPopupEvent, PopupPriority, TextPopupPosition};
+use crate::inventory::{Inventory, Wallet};
+use crate::party::{MemberSave, PartyMember};
+use crate::player::{xp_required_for_level, Player};
+use crate::quest::QuestLog;
+use crate::status::StatusEffects;
+use crate::tilemap::{CurrentMap, DefeatedBosses};
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+pub struct SavePlugin;
+
+/// Bumped whenever the save layout changes in a way old files can't be read with
+pub const SAVE_VERSION: u32 = 1;
+
+pub(crate) const SAVE_DIR: &str = "ludyrpg";
+const SAVE_FILE: &str = "save.ron";
+/// Written first and renamed over the save, so a failed write never costs the old one
+const SAVE_TEMP_FILE: &str = "save.ron.tmp";
+
+/// The world has no chests or doors that stay open, what it remembers is the beaten bosses, the
+/// recruited party, the story flags and variables and the quests. Anything added later needs a
+/// serde default so older saves still load
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveData {
+    version: u32,
+    pub(crate) player: PlayerSave,
+    pub(crate) map: String,
+    pub(crate) position: (f32, f32),
+    /// Boss ids as `CurrentMap::boss_id` builds them
+    #[serde(default)]
+    pub(crate) defeated_bosses: Vec<String>,
+    /// Recruited companions in the order they follow the player
+    #[serde(default)]
+    pub(crate) party: Vec<MemberSave>,
+    /// Story flags set through dialogue
+    #[serde(default)]
+    pub(crate) flags: Vec<String>,
+    /// Sorted by name so the save doesn't shuffle around between writes
+    #[serde(default)]
+    pub(crate) variables: BTreeMap<String, isize>,
+    #[serde(default)]
+    pub(crate) quests: QuestLog,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlayerSave {
+    pub(crate) level: usize,
+    pub(crate) exp: usize,
+    pub(crate) stats: CombatStats,
+    /// Saves from before the inventory existed start out empty handed
+    #[serde(default)]
+    pub(crate) inventory: Inventory,
+    #[serde(default)]
+    pub(crate) equipment: Equipment,
+    #[serde(default)]
+    pub(crate) wallet: Wallet,
+    /// Only the persistent ones are left outside of a fight
+    #[serde(default)]
+    pub(crate) statuses: StatusEffects,
+}
+
+/// Save picked with "Continue" on the start menu, `None` for a new game
+#[derive(Default)]
+pub struct LoadedGame(pub(crate) Option<SaveData>);
+
+pub struct SaveGameEvent;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadedGame>()
+            .add_event::<SaveGameEvent>()
+            .add_system(handle_save_game_event);
+    }
+}
+
+impl SaveData {
+    pub fn levelup_percentage(&self) -> f32 {
+        self.player.exp as f32 / xp_required_for_level(self.player.level) as f32
+    }
+}
+
+fn save_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(SAVE_DIR).join(SAVE_FILE))
+}
+
+pub fn save_exists() -> bool {
+    save_path().is_some_and(|path| path.exists())
+}
+
+pub fn read_save() -> Result<SaveData, anyhow::Error> {
+    let path = save_path().context("no data directory on this platform")?;
+    let bytes = fs::read(&path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let save: SaveData =
+        ron::de::from_bytes(&bytes).with_context(|| format!("corrupt save {}", path.display()))?;
+
+    if save.version > SAVE_VERSION {
+        bail!(
+            "save version {} is newer than this game supports ({})",
+            save.version,
+            SAVE_VERSION
+        );
+    }
+
+    Ok(save)
+}
+
+fn write_save(save: &SaveData) -> Result<(), anyhow::Error> {
+    let path = save_path().context("no data directory on this platform")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let text = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+    let temp_path = path.with_file_name(SAVE_TEMP_FILE);
+    fs::write(&temp_path, text)
+        .with_context(|| format!("couldn't write {}", temp_path.display()))?;
+    fs::rename(&temp_path, &path)
+        .with_context(|| format!("couldn't replace {}", path.display()))?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_save_game_event(
+    mut ev_save_game: EventReader<SaveGameEvent>,
+    player_query: Query<(
+        &Player,
+        &CombatStats,
+        &Inventory,
+        &Equipment,
+        &Wallet,
+        &StatusEffects,
+        &Transform,
+    )>,
+    member_query: Query<(&PartyMember, &CombatStats, &StatusEffects)>,
+    current_map: Res<CurrentMap>,
+    defeated_bosses: Res<DefeatedBosses>,
+    story: Res<StoryState>,
+    quests: Res<QuestLog>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    if ev_save_game.iter().count() == 0 {
+        return;
+    }
+
+    let (player, stats, inventory, equipment, wallet, statuses, transform) = player_query.single();
+    let save = SaveData {
+        version: SAVE_VERSION,
+        player: PlayerSave {
+            level: player.level,
+            exp: player.exp,
+            stats: stats.clone(),
+            inventory: inventory.clone(),
+            equipment: equipment.clone(),
+            wallet: wallet.clone(),
+            statuses: statuses.clone(),
+        },
+        map: current_map.name.clone(),
+        position: (transform.translation.x, transform.translation.y),
+        defeated_bosses: {
+            // Sorted so the save doesn't shuffle around between writes
+            let mut bosses: Vec<String> = defeated_bosses.0.iter().cloned().collect();
+            bosses.sort();
+            bosses
+        },
+        party: {
+            // Queries come in no particular order, the save keeps the line the members walk in
+            let mut members: Vec<(&PartyMember, &CombatStats, &StatusEffects)> =
+                member_query.iter().collect();
+            members.sort_by_key(|(member, _, _)| member.slot);
+            members
+                .into_iter()
+                .map(|(member, stats, statuses)| MemberSave {
+                    id: member.id.clone(),
+                    level: member.level,
+                    exp: member.exp,
+                    stats: stats.clone(),
+                    statuses: statuses.clone(),
+                })
+                .collect()
+        },
+        flags: {
+            let mut flags: Vec<String> = story.flags.iter().cloned().collect();
+            flags.sort();
+            flags
+        },
+        variables: story
+            .variables
+            .iter()
+            .map(|(variable, value)| (variable.clone(), *value))
+            .collect(),
+        quests: quests.clone(),
+    };
+
+    // Told only once the file is written, so the player never hears both
+    match write_save(&save) {
+        Ok(()) => ev_text_popup.send(CreateTextPopupEvent {
+            text: "Game saved.".to_string(),
+            position: TextPopupPosition::Left,
+            duration: 2.0,
+            ..default()
+        }),
+        Err(err) => {
+            error!("Saving failed: {:#}", err);
+            ev_text_popup.send(CreateTextPopupEvent {
+                text: "Couldn't save the game!".to_string(),
+                position: TextPopupPosition::Left,
+                priority: PopupPriority::High,
+                sticky: true,
+                ..default()
+            });
+        }
+    }
+}
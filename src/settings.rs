@@ -0,0 +1,500 @@
+use crate::game_ui::UiAssets;
+use crate::save::SAVE_DIR;
+use crate::{GameState, RESOLUTION, WINDOW_HEIGHT};
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+use bevy::window::WindowMode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct SettingsPlugin;
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+/// How much a volume slider moves per key press or click
+const VOLUME_STEP: f32 = 0.05;
+
+/// Multiples of the default window size the player can pick from
+const WINDOW_SCALES: [f32; 5] = [0.75, 1.0, 1.25, 1.5, 2.0];
+
+/// Player preferences, kept in a config file next to the save rather than in it
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub(crate) bgm_volume: f32,
+    pub(crate) combat_music_volume: f32,
+    pub(crate) sfx_volume: f32,
+    pub(crate) muted: bool,
+    pub(crate) window_scale: f32,
+    pub(crate) fullscreen: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SettingsRow {
+    BgmVolume,
+    CombatMusicVolume,
+    SfxVolume,
+    Mute,
+    WindowScale,
+    Fullscreen,
+    Back,
+}
+
+/// Rows from the top down
+const SETTINGS_ROWS: [(&str, SettingsRow); 7] = [
+    ("Music", SettingsRow::BgmVolume),
+    ("Combat music", SettingsRow::CombatMusicVolume),
+    ("Sound effects", SettingsRow::SfxVolume),
+    ("Mute", SettingsRow::Mute),
+    ("Window size", SettingsRow::WindowScale),
+    ("Fullscreen", SettingsRow::Fullscreen),
+    ("Back", SettingsRow::Back),
+];
+
+/// The row the keyboard is on, only around while the menu is open
+struct SettingsSelection {
+    selected: usize,
+}
+
+#[derive(Component)]
+struct SettingsMenu;
+
+/// Hovering selects the row, clicking changes it by `step`, which is 0 for the row itself
+#[derive(Component)]
+struct SettingsButton {
+    row: usize,
+    step: isize,
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_window_settings)
+            .add_system(save_settings)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Settings).with_system(open_settings_menu),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(settings_menu_input)
+                    .with_system(update_settings_menu.after(settings_menu_input)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Settings).with_system(close_settings_menu),
+            );
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            bgm_volume: 0.05,
+            combat_music_volume: 0.2,
+            sfx_volume: 0.1,
+            muted: false,
+            window_scale: 1.0,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Settings {
+    /// What a channel at this volume should actually play at
+    pub fn volume(&self, volume: f32) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            volume
+        }
+    }
+
+    pub fn window_size(&self) -> (f32, f32) {
+        let height = WINDOW_HEIGHT * self.window_scale;
+        (height * RESOLUTION, height)
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    /// Moves a slider `step` notches along, toggles just flip
+    fn change(&mut self, row: SettingsRow, step: isize) {
+        match row {
+            SettingsRow::BgmVolume => self.bgm_volume = step_volume(self.bgm_volume, step),
+            SettingsRow::CombatMusicVolume => {
+                self.combat_music_volume = step_volume(self.combat_music_volume, step)
+            }
+            SettingsRow::SfxVolume => self.sfx_volume = step_volume(self.sfx_volume, step),
+            SettingsRow::Mute => self.muted = !self.muted,
+            SettingsRow::WindowScale => {
+                let current = closest_scale(self.window_scale);
+                let new_index =
+                    (current as isize + step).clamp(0, WINDOW_SCALES.len() as isize - 1);
+                self.window_scale = WINDOW_SCALES[new_index as usize];
+            }
+            SettingsRow::Fullscreen => self.fullscreen = !self.fullscreen,
+            SettingsRow::Back => {}
+        }
+    }
+
+    /// Pulls hand edited values back into what the menu can show and the window can take
+    fn sanitize(&mut self) {
+        for volume in [
+            &mut self.bgm_volume,
+            &mut self.combat_music_volume,
+            &mut self.sfx_volume,
+        ] {
+            *volume = if volume.is_finite() {
+                volume.clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+        }
+        self.window_scale = WINDOW_SCALES[closest_scale(self.window_scale)];
+    }
+
+    /// Shown next to the row's label
+    fn value_text(&self, row: SettingsRow) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" }.to_string();
+        match row {
+            SettingsRow::BgmVolume => volume_bar(self.bgm_volume),
+            SettingsRow::CombatMusicVolume => volume_bar(self.combat_music_volume),
+            SettingsRow::SfxVolume => volume_bar(self.sfx_volume),
+            SettingsRow::Mute => on_off(self.muted),
+            SettingsRow::WindowScale => {
+                let (width, height) = self.window_size();
+                format!("{:.0}x{:.0}", width, height)
+            }
+            SettingsRow::Fullscreen => on_off(self.fullscreen),
+            SettingsRow::Back => String::new(),
+        }
+    }
+}
+
+/// Index of the window scale nearest to `scale`, a NaN lands on the first one
+fn closest_scale(scale: f32) -> usize {
+    WINDOW_SCALES
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - scale).abs().total_cmp(&(*b - scale).abs()))
+        .map(|(i, _)| i)
+        .unwrap()
+}
+
+/// Rounded to whole steps so that going up and back down lands on the same value
+pub fn step_volume(volume: f32, step: isize) -> f32 {
+    let steps = (volume / VOLUME_STEP).round() + step as f32;
+    (steps * VOLUME_STEP).clamp(0.0, 1.0)
+}
+
+fn volume_bar(volume: f32) -> String {
+    let filled = (volume * 10.0).round() as usize;
+    format!(
+        "{}{} {:>3}%",
+        "#".repeat(filled),
+        "-".repeat(10 - filled),
+        (volume * 100.0).round()
+    )
+}
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(SAVE_DIR).join(SETTINGS_FILE))
+}
+
+/// The defaults when there's no config yet
+pub fn read_settings() -> Result<Settings, anyhow::Error> {
+    let path = settings_path().context("no config directory on this platform")?;
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let bytes = fs::read(&path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let mut settings: Settings = ron::de::from_bytes(&bytes)
+        .with_context(|| format!("corrupt settings {}", path.display()))?;
+    settings.sanitize();
+
+    Ok(settings)
+}
+
+fn write_settings(settings: &Settings) -> Result<(), anyhow::Error> {
+    let path = settings_path().context("no config directory on this platform")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let text = ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default())?;
+    fs::write(&path, text).with_context(|| format!("couldn't write {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Every change is written out right away, there's no apply button to forget
+fn save_settings(settings: Res<Settings>) {
+    // Added means it was just read from the file
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    if let Err(err) = write_settings(&settings) {
+        error!("Saving the settings failed: {:#}", err);
+    }
+}
+
+fn apply_window_settings(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+    if window.mode() != settings.window_mode() {
+        window.set_mode(settings.window_mode());
+    }
+    let (width, height) = settings.window_size();
+    window.set_resolution(width, height);
+}
+
+fn open_settings_menu(mut commands: Commands) {
+    commands.insert_resource(SettingsSelection { selected: 0 });
+}
+
+fn close_settings_menu(mut commands: Commands, menu_query: Query<Entity, With<SettingsMenu>>) {
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+    commands.remove_resource::<SettingsSelection>();
+}
+
+fn settings_menu_input(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    selection: Option<ResMut<SettingsSelection>>,
+    interaction_query: Query<(&SettingsButton, &Interaction), Changed<Interaction>>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let mut selection = match selection {
+        Some(selection) => selection,
+        None => return,
+    };
+
+    // (row, step) to change
+    let mut chosen = None;
+    for (button, interaction) in interaction_query.iter() {
+        match interaction {
+            Interaction::Clicked => chosen = Some((button.row, button.step)),
+            Interaction::Hovered if selection.selected != button.row => {
+                selection.selected = button.row;
+            }
+            _ => {}
+        }
+    }
+
+    let count = SETTINGS_ROWS.len();
+    if keyboard.just_pressed(KeyCode::W) {
+        selection.selected = (selection.selected + count - 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::S) {
+        selection.selected = (selection.selected + 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::A) {
+        chosen = Some((selection.selected, -1));
+    }
+    if keyboard.just_pressed(KeyCode::D) {
+        chosen = Some((selection.selected, 1));
+    }
+    if keyboard.clear_just_pressed(KeyCode::E) || keyboard.clear_just_pressed(KeyCode::Return) {
+        chosen = Some((selection.selected, 0));
+    }
+    // Back to the pause menu, which has to see this press gone as well
+    if keyboard.clear_just_pressed(KeyCode::Escape) {
+        chosen = Some((count - 1, 0));
+    }
+
+    let (row, step) = match chosen {
+        Some(chosen) => chosen,
+        None => return,
+    };
+    let row = SETTINGS_ROWS[row].1;
+    match row {
+        SettingsRow::Back => {
+            let _ = state.pop();
+        }
+        // Toggles flip whichever way they're pushed, sliders need a direction
+        SettingsRow::Mute | SettingsRow::Fullscreen => settings.change(row, step),
+        _ if step != 0 => settings.change(row, step),
+        _ => {}
+    }
+}
+
+/// Respawns the whole menu whenever a setting or the selection changes
+fn update_settings_menu(
+    mut commands: Commands,
+    selection: Option<Res<SettingsSelection>>,
+    settings: Res<Settings>,
+    menu_query: Query<Entity, With<SettingsMenu>>,
+    ui_assets: Res<UiAssets>,
+) {
+    let selection = match selection {
+        Some(selection) => selection,
+        None => return,
+    };
+    if !selection.is_changed() && !settings.is_changed() {
+        return;
+    }
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+
+    let text_style = TextStyle {
+        font: ui_assets.font.clone(),
+        font_size: 32.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    let selected_style = TextStyle {
+        color: Color::GOLD,
+        ..text_style.clone()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+            ..default()
+        })
+        .insert(SettingsMenu)
+        .insert(Name::new("Settings Menu"))
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Percent(2.0)),
+                    ..default()
+                },
+                text: Text::with_section(
+                    "Settings",
+                    TextStyle {
+                        font: ui_assets.font_bold.clone(),
+                        font_size: 50.0,
+                        color: Color::GOLD,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+
+            for (i, (label, row)) in SETTINGS_ROWS.iter().enumerate() {
+                let style = if i == selection.selected {
+                    &selected_style
+                } else {
+                    &text_style
+                };
+                let value = settings.value_text(*row);
+                spawn_settings_row(parent, i, label, &value, *row != SettingsRow::Back, style);
+            }
+        });
+}
+
+/// A whole line is one button, with `<` and `>` buttons around the value when there is one
+fn spawn_settings_row(
+    parent: &mut ChildBuilder,
+    row: usize,
+    label: &str,
+    value: &str,
+    arrows: bool,
+    style: &TextStyle,
+) {
+    let text = |text: &str| TextBundle {
+        text: Text::with_section(text, style.clone(), default()),
+        focus_policy: FocusPolicy::Pass,
+        ..default()
+    };
+    let column = |width: f32| NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(width), Val::Auto),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::NONE.into(),
+        focus_policy: FocusPolicy::Pass,
+        ..default()
+    };
+    let arrow = |parent: &mut ChildBuilder, symbol: &str, step: isize| {
+        parent
+            .spawn_bundle(ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(40.0), Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                color: Color::NONE.into(),
+                ..default()
+            })
+            .insert(SettingsButton { row, step })
+            .with_children(|parent| {
+                parent.spawn_bundle(text(symbol));
+            });
+    };
+
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                margin: Rect::all(Val::Px(4.0)),
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(SettingsButton { row, step: 0 })
+        .with_children(|parent| {
+            parent.spawn_bundle(column(260.0)).with_children(|parent| {
+                parent.spawn_bundle(text(label));
+            });
+            if arrows {
+                arrow(parent, "<", -1);
+                parent.spawn_bundle(column(260.0)).with_children(|parent| {
+                    parent.spawn_bundle(text(value));
+                });
+                arrow(parent, ">", 1);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hand_edited_values_are_pulled_back_in() {
+        let mut settings: Settings =
+            ron::from_str("(bgm_volume: 3.0, sfx_volume: -1.0, window_scale: 0.0)").unwrap();
+        settings.sanitize();
+        assert_eq!(settings.bgm_volume, 1.0);
+        assert_eq!(settings.sfx_volume, 0.0);
+        assert_eq!(settings.window_scale, WINDOW_SCALES[0]);
+        // Would underflow the bar before the volume was clamped
+        volume_bar(settings.bgm_volume);
+    }
+
+    #[test]
+    fn volume_steps_round_trip() {
+        let volume = step_volume(step_volume(0.05, 3), -3);
+        assert!((volume - 0.05).abs() < f32::EPSILON);
+        assert_eq!(step_volume(1.0, 1), 1.0);
+        assert_eq!(step_volume(0.0, -1), 0.0);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::ascii::{
+    spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, AsciiSheet, NineSliceIndices,
+};
+use crate::data::RonAssetLoader;
+use crate::game_ui::{CreateTextPopupEvent, TextPopupPosition};
+use crate::inventory::{item_name, Inventory, ItemAssets, ItemDatabase, Wallet};
+use crate::player::Player;
+use crate::{GameState, TILE_SIZE};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::Camera2d;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+pub struct ShopPlugin;
+
+/// Characters that fit on a line of the shop menu, the cursor and borders come on top
+const SHOP_LINE_WIDTH: usize = 22;
+
+/// Stock of every shop keyed by the id its shopkeeper is given, loaded from `assets/data/shops.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "b4e8a2d6-1c7f-4e39-9a05-6d2f8c1e7b53"]
+pub struct ShopDatabase {
+    shops: HashMap<String, ShopDefinition>,
+}
+
+#[derive(Deserialize)]
+pub struct ShopDefinition {
+    /// Item ids in the order they're listed, prices come from the item database
+    stock: Vec<String>,
+}
+
+pub struct ShopAssets {
+    database: Handle<ShopDatabase>,
+}
+
+/// Sent by a shopkeeper NPC to open its shop
+pub struct OpenShopEvent {
+    pub(crate) id: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ShopTab {
+    Buy,
+    Sell,
+}
+
+/// The shop being browsed, only around while its menu is open
+pub struct OpenShop {
+    id: String,
+    tab: ShopTab,
+    selected: usize,
+}
+
+#[derive(Component)]
+pub struct ShopMenu;
+
+impl Plugin for ShopPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ShopDatabase>()
+            .add_asset_loader(RonAssetLoader::<ShopDatabase>::new(&["shops.ron"]))
+            .add_event::<OpenShopEvent>()
+            .add_startup_system(load_shop_database)
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(open_shop)
+                    .with_system(shop_input)
+                    .with_system(update_shop_menu.after(shop_input))
+                    .with_system(shop_menu_follow_camera.after(update_shop_menu)),
+            );
+    }
+}
+
+fn load_shop_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(ShopAssets {
+        database: assets.load("data/shops.ron"),
+    });
+}
+
+/// Items listed on the current tab with what they go for
+fn shop_entries(
+    shop: &OpenShop,
+    shops: &ShopDatabase,
+    items: &ItemDatabase,
+    inventory: &Inventory,
+) -> Vec<(String, usize)> {
+    match shop.tab {
+        ShopTab::Buy => shops
+            .shops
+            .get(&shop.id)
+            .map(|definition| {
+                definition
+                    .stock
+                    .iter()
+                    .filter_map(|item| Some((item.clone(), items.get(item)?.price)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ShopTab::Sell => inventory
+            .items()
+            .iter()
+            .filter_map(|stack| {
+                let price = items.get(&stack.item)?.price / 2;
+                (price > 0).then(|| (stack.item.clone(), price))
+            })
+            .collect(),
+    }
+}
+
+fn open_shop(
+    mut commands: Commands,
+    mut ev_open_shop: EventReader<OpenShopEvent>,
+    mut player_query: Query<&mut Player>,
+    shop_assets: Res<ShopAssets>,
+    shop_databases: Res<Assets<ShopDatabase>>,
+) {
+    for event in ev_open_shop.iter() {
+        let known = shop_databases
+            .get(&shop_assets.database)
+            .is_some_and(|database| database.shops.contains_key(&event.id));
+        if !known {
+            error!("Tried to open unknown shop '{}'", event.id);
+            continue;
+        }
+
+        // The menu shows up next frame, so the key that opened it can't buy anything yet
+        player_query.single_mut().active = false;
+        commands.insert_resource(OpenShop {
+            id: event.id.clone(),
+            tab: ShopTab::Buy,
+            selected: 0,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shop_input(
+    mut commands: Commands,
+    shop: Option<ResMut<OpenShop>>,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut player_query: Query<(&mut Player, &mut Inventory, &mut Wallet)>,
+    shop_assets: Res<ShopAssets>,
+    shop_databases: Res<Assets<ShopDatabase>>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    let mut shop = match shop {
+        Some(shop) => shop,
+        None => return,
+    };
+    let (mut player, mut inventory, mut wallet) = player_query.single_mut();
+
+    if keyboard.clear_just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<OpenShop>();
+        player.active = true;
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::A) || keyboard.just_pressed(KeyCode::D) {
+        shop.tab = match shop.tab {
+            ShopTab::Buy => ShopTab::Sell,
+            ShopTab::Sell => ShopTab::Buy,
+        };
+        shop.selected = 0;
+        return;
+    }
+
+    let (shops, items) = match (
+        shop_databases.get(&shop_assets.database),
+        item_databases.get(&item_assets.database),
+    ) {
+        (Some(shops), Some(items)) => (shops, items),
+        _ => return,
+    };
+    let entries = shop_entries(&shop, shops, items, &inventory);
+    if entries.is_empty() {
+        return;
+    }
+
+    let count = entries.len() as isize;
+    let mut new_index = shop.selected.min(entries.len() - 1) as isize;
+    if keyboard.just_pressed(KeyCode::W) {
+        new_index -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::S) {
+        new_index += 1;
+    }
+    let new_index = ((new_index + count) % count) as usize;
+    if new_index != shop.selected {
+        shop.selected = new_index;
+    }
+
+    if !keyboard.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    let (item, price) = &entries[shop.selected];
+    let name = item_name(Some(items), item);
+    let text = match shop.tab {
+        ShopTab::Buy if wallet.gold < *price => "You can't afford that.".to_string(),
+        ShopTab::Buy if !inventory.can_carry(item) => {
+            format!("You can't carry any more of {}.", name)
+        }
+        ShopTab::Buy => {
+            wallet.gold -= price;
+            inventory.add(item, 1);
+            format!("Bought {} for {} gold.", name, price)
+        }
+        ShopTab::Sell => {
+            inventory.remove_one(item);
+            wallet.gold += price;
+            // Selling the last of an item takes its line away
+            if inventory.count(item) == 0 {
+                shop.selected = shop.selected.min(entries.len().saturating_sub(2));
+            }
+            format!("Sold {} for {} gold.", name, price)
+        }
+    };
+    // The menu is redrawn from the shop, so it has to notice the gold and items changing
+    shop.set_changed();
+
+    ev_text_popup.send(CreateTextPopupEvent {
+        text,
+        position: TextPopupPosition::Left,
+        duration: 2.0,
+        ..default()
+    });
+}
+
+/// Respawns the whole menu whenever the shop changes, and takes it away once it's closed
+#[allow(clippy::too_many_arguments)]
+fn update_shop_menu(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    nine_slice_indices: Res<NineSliceIndices>,
+    shop: Option<Res<OpenShop>>,
+    menu_query: Query<Entity, With<ShopMenu>>,
+    player_query: Query<(&Inventory, &Wallet), With<Player>>,
+    shop_assets: Res<ShopAssets>,
+    shop_databases: Res<Assets<ShopDatabase>>,
+    item_assets: Res<ItemAssets>,
+    item_databases: Res<Assets<ItemDatabase>>,
+) {
+    if shop.as_ref().is_some_and(|shop| !shop.is_changed()) {
+        return;
+    }
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+
+    let (shop, shops, items) = match (
+        shop,
+        shop_databases.get(&shop_assets.database),
+        item_databases.get(&item_assets.database),
+    ) {
+        (Some(shop), Some(shops), Some(items)) => (shop, shops, items),
+        _ => return,
+    };
+    let (inventory, wallet) = player_query.single();
+    let entries = shop_entries(&shop, shops, items, inventory);
+    let selected = shop.selected.min(entries.len().saturating_sub(1));
+
+    let tabs = match shop.tab {
+        ShopTab::Buy => "[Buy]  Sell",
+        ShopTab::Sell => " Buy  [Sell]",
+    };
+    let mut lines = vec![shop_line(tabs, wallet.gold), String::new()];
+    if entries.is_empty() {
+        lines.push("Nothing to sell.".to_string());
+    }
+    for (item, price) in entries.iter() {
+        lines.push(shop_line(&item_name(Some(items), item), *price));
+    }
+    lines.push(String::new());
+    lines.push(match entries.get(selected) {
+        Some((item, _)) => format!("Owned x{}", inventory.count(item)),
+        None => String::new(),
+    });
+
+    let width = (SHOP_LINE_WIDTH + 4) as f32;
+    let height = (lines.len() + 2) as f32;
+    let line_y = |line: usize| (height / 2.0 - 1.5 - line as f32) * TILE_SIZE;
+
+    let mut children = vec![spawn_nine_slice(
+        &mut commands,
+        &ascii,
+        &nine_slice_indices,
+        width,
+        height,
+    )];
+    for (i, line) in lines.iter().enumerate() {
+        children.push(spawn_ascii_text(
+            &mut commands,
+            &ascii,
+            line,
+            Vec3::new((-width / 2.0 + 2.5) * TILE_SIZE, line_y(i), 1.0),
+        ));
+    }
+    if !entries.is_empty() {
+        // ► in the ascii sheet, entries start under the header and a blank line
+        children.push(spawn_ascii_sprite(
+            &mut commands,
+            &ascii,
+            16,
+            Color::RED,
+            Vec3::new((-width / 2.0 + 1.5) * TILE_SIZE, line_y(selected + 2), 1.0),
+            Vec3::splat(1.0),
+        ));
+    }
+
+    commands
+        .spawn()
+        .insert(Transform::default())
+        .insert(GlobalTransform::default())
+        .insert(Name::new("ShopMenu"))
+        .insert(ShopMenu)
+        .push_children(&children);
+}
+
+/// Text on the left and gold aligned to the right edge
+fn shop_line(text: &str, gold: usize) -> String {
+    format!("{:<width$}{:>6} G", text, gold, width = SHOP_LINE_WIDTH - 8)
+}
+
+/// Keeps the menu in the middle of the screen however the camera moves or zooms
+fn shop_menu_follow_camera(
+    camera_query: Query<&Transform, With<Camera2d>>,
+    mut menu_query: Query<&mut Transform, (With<ShopMenu>, Without<Camera2d>)>,
+) {
+    let camera_transform = camera_query.single();
+    for mut transform in menu_query.iter_mut() {
+        transform.translation = camera_transform.translation.truncate().extend(110.0);
+        transform.scale = camera_transform.scale;
+    }
+}
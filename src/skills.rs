@@ -0,0 +1,302 @@
+use crate::ascii::{AsciiSheet, NineSliceIndices};
+use crate::combat::{
+    spawn_combat_list_menu, AttackEvent, AttackStyle, CombatListMenu, CombatState, CombatStats,
+    Element, LevelupEvent, PlayerAttackChoice,
+};
+use crate::data::RonAssetLoader;
+use crate::game_ui::{CreateTextPopupEvent, PopupPriority, TextPopupPosition};
+use crate::player::Player;
+use crate::status::{Infliction, StatusEffect};
+use anyhow::bail;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+pub struct SkillPlugin;
+
+/// Skill definitions keyed by id and the levels they're learned at,
+/// loaded from `assets/data/skills.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "e2a4c6b8-1f3d-4e5a-9b7c-0d2f4a6c8e91"]
+pub struct SkillDatabase {
+    skills: HashMap<String, SkillDefinition>,
+    /// (level, skill id) pairs
+    learnset: Vec<(usize, String)>,
+}
+
+#[derive(Deserialize)]
+pub struct SkillDefinition {
+    name: String,
+    mp_cost: isize,
+    effect: SkillEffect,
+}
+
+#[derive(Deserialize)]
+pub enum SkillEffect {
+    /// Hits a picked enemy
+    Attack(AttackStyle),
+    /// Restores up to this much health to the user
+    Heal(isize),
+    /// Puts a status effect on the user
+    Buff(StatusEffect),
+}
+
+pub struct SkillAssets {
+    database: Handle<SkillDatabase>,
+}
+
+/// Skill ids the player can use, in the order they were learned
+#[derive(Component, Default)]
+pub struct Skills {
+    known: Vec<String>,
+}
+
+impl Plugin for SkillPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<SkillDatabase>()
+            .add_asset_loader(
+                RonAssetLoader::<SkillDatabase>::new(&["skills.ron"])
+                    .validated(SkillDatabase::validate),
+            )
+            .add_startup_system(load_skill_database)
+            .add_system(learn_skills)
+            .add_system_set(
+                SystemSet::on_enter(CombatState::PlayerSkill).with_system(spawn_combat_skill_menu),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::PlayerSkill).with_system(combat_skill_input),
+            );
+    }
+}
+
+impl SkillDatabase {
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        for (id, skill) in self.skills.iter() {
+            if skill.mp_cost < 0 {
+                bail!("skill '{}' costs {} MP", id, skill.mp_cost);
+            }
+            match &skill.effect {
+                SkillEffect::Heal(amount) if *amount <= 0 => {
+                    bail!("skill '{}' heals {}", id, amount)
+                }
+                SkillEffect::Buff(status) if status.turns == 0 => {
+                    bail!("skill '{}' buffs for 0 turns", id)
+                }
+                _ => {}
+            }
+        }
+
+        // A skill missing here would be skipped without a word and never learned
+        for (level, skill) in self.learnset.iter() {
+            if !self.skills.contains_key(skill) {
+                bail!("learnset names unknown skill '{}'", skill);
+            }
+            if *level == 0 {
+                bail!("skill '{}' is learned at level 0", skill);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_skill_database(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(SkillAssets {
+        database: assets.load("data/skills.ron"),
+    });
+}
+
+/// Catches up on every skill up to the player's level, so saves don't need to store them
+fn learn_skills(
+    mut ev_levelup: EventReader<LevelupEvent>,
+    mut ev_asset: EventReader<AssetEvent<SkillDatabase>>,
+    mut player_query: Query<(&Player, &mut Skills, ChangeTrackers<Skills>)>,
+    skill_assets: Res<SkillAssets>,
+    skill_databases: Res<Assets<SkillDatabase>>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    let leveled_up = ev_levelup.iter().count() > 0;
+    // A player spawned before the database was there still has to catch up once it is
+    let loaded = ev_asset
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Created { .. }));
+    let database = match skill_databases.get(&skill_assets.database) {
+        Some(database) => database,
+        None => return,
+    };
+
+    for (player, mut skills, tracker) in player_query.iter_mut() {
+        if !leveled_up && !loaded && !tracker.is_added() {
+            continue;
+        }
+
+        for (level, skill) in database.learnset.iter() {
+            if *level > player.level || skills.known.contains(skill) {
+                continue;
+            }
+
+            skills.known.push(skill.clone());
+            if let (true, Some(definition)) = (leveled_up, database.skills.get(skill)) {
+                ev_text_popup.send(CreateTextPopupEvent {
+                    text: format!("Learned {}!", definition.name),
+                    position: TextPopupPosition::Center,
+                    duration: 2.5,
+                    priority: PopupPriority::High,
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+fn spawn_combat_skill_menu(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    nine_slice_indices: Res<NineSliceIndices>,
+    player_query: Query<(&Skills, &CombatStats), With<Player>>,
+    skill_assets: Res<SkillAssets>,
+    skill_databases: Res<Assets<SkillDatabase>>,
+) {
+    let (skills, stats) = player_query.single();
+    let database = skill_databases
+        .get(&skill_assets.database)
+        .expect("Skill database is not loaded!");
+
+    let name_width = skills
+        .known
+        .iter()
+        .filter_map(|skill| database.skills.get(skill))
+        .map(|definition| definition.name.len())
+        .max()
+        .unwrap_or(0);
+    let entries: Vec<(String, String)> = skills
+        .known
+        .iter()
+        .filter_map(|skill| Some((skill, database.skills.get(skill)?)))
+        .map(|(skill, definition)| {
+            let line = format!(
+                "{:width$} {}MP",
+                definition.name,
+                definition.mp_cost,
+                width = name_width
+            );
+            (skill.clone(), line)
+        })
+        .collect();
+
+    spawn_combat_list_menu(
+        &mut commands,
+        &ascii,
+        &nine_slice_indices,
+        "SkillMenu",
+        Some(format!("MP {}/{}", stats.mp, stats.max_mp)),
+        entries,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn combat_skill_input(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut player_query: Query<(Entity, &mut CombatStats), With<Player>>,
+    menu_query: Query<&CombatListMenu>,
+    skill_assets: Res<SkillAssets>,
+    skill_databases: Res<Assets<SkillDatabase>>,
+    mut attack_choice: ResMut<PlayerAttackChoice>,
+    mut combat_state: ResMut<State<CombatState>>,
+    mut fight_event: EventWriter<AttackEvent>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
+) {
+    // Cleared so target selection doesn't see the same press after the state change
+    if !keyboard.clear_just_pressed(KeyCode::E) {
+        return;
+    }
+
+    let definition = match (
+        menu_query
+            .get_single()
+            .ok()
+            .and_then(CombatListMenu::selected),
+        skill_databases.get(&skill_assets.database),
+    ) {
+        (Some(skill), Some(database)) => match database.skills.get(skill) {
+            Some(definition) => definition,
+            None => return,
+        },
+        _ => return,
+    };
+
+    let (player, mut stats) = player_query.single_mut();
+    if stats.mp < definition.mp_cost {
+        ev_text_popup.send(CreateTextPopupEvent {
+            text: "Not enough MP!".to_string(),
+            position: TextPopupPosition::Center,
+            duration: 1.5,
+            ..default()
+        });
+        return;
+    }
+
+    match &definition.effect {
+        SkillEffect::Attack(style) => {
+            // Paid for once a target is picked
+            *attack_choice = PlayerAttackChoice {
+                style: style.clone(),
+                mp_cost: definition.mp_cost,
+            };
+            combat_state.set(CombatState::PlayerTarget).unwrap();
+        }
+        SkillEffect::Heal(amount) => {
+            stats.mp -= definition.mp_cost;
+            fight_event.send(AttackEvent {
+                target: player,
+                damage_amount: -amount,
+                hits: 1,
+                pierce: true,
+                inflicts: None,
+                crit_chance: 0.0,
+                element: Element::Physical,
+                next_state: CombatState::PlayerAttack,
+            });
+        }
+        SkillEffect::Buff(status) => {
+            stats.mp -= definition.mp_cost;
+            fight_event.send(AttackEvent {
+                target: player,
+                damage_amount: 0,
+                hits: 1,
+                pierce: true,
+                inflicts: Some(Infliction {
+                    status: status.clone(),
+                    chance: 1.0,
+                }),
+                crit_chance: 0.0,
+                element: Element::Physical,
+                next_state: CombatState::PlayerAttack,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_skills_are_valid() {
+        let database: SkillDatabase =
+            ron::from_str(include_str!("../assets/data/skills.ron")).unwrap();
+        if let Err(err) = database.validate() {
+            panic!("{:#}", err);
+        }
+    }
+
+    #[test]
+    fn unknown_learnset_skill_is_rejected() {
+        let database: SkillDatabase = ron::from_str(
+            r#"(skills: {"heal": (name: "Heal", mp_cost: 3, effect: Heal(6))}, learnset: [(2, "hael")])"#,
+        )
+        .unwrap();
+        assert!(database.validate().is_err());
+    }
+}
@@ -2,7 +2,8 @@ use crate::ascii::AsciiSheet;
 use crate::audio::{AudioState, BgmChannel};
 use crate::combat::CombatState;
 use crate::fadeout::create_fadeout;
-use crate::game_ui::UiAssets;
+use crate::game_ui::{CreateTextPopupEvent, PopupPriority, TextPopupPosition, UiAssets};
+use crate::save::{read_save, save_exists, LoadedGame};
 use crate::GameState;
 use bevy::prelude::*;
 use bevy::ui::FocusPolicy;
@@ -16,10 +17,18 @@ pub struct ButtonActive(bool);
 #[derive(Component)]
 pub struct StartMenuButton;
 
+#[derive(Component)]
+struct StartMenu;
+
+#[derive(Component, Clone, Copy)]
+enum StartMenuAction {
+    Continue,
+    NewGame,
+}
+
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_pause(GameState::StartMenu).with_system(despawn_menu))
-            .add_system_set(SystemSet::on_update(GameState::Overworld).with_system(return_to_menu))
             .add_system_set(SystemSet::on_update(CombatState::Dead).with_system(return_to_menu))
             .add_system_set(
                 SystemSet::on_enter(GameState::StartMenu)
@@ -58,31 +67,59 @@ fn return_to_menu(mut commands: Commands, ascii: Res<AsciiSheet>, keyboard: Res<
     }
 }
 
-fn despawn_menu(mut commands: Commands, button_query: Query<Entity, With<Button>>) {
-    for ent in button_query.iter() {
+fn despawn_menu(mut commands: Commands, menu_query: Query<Entity, With<StartMenu>>) {
+    for ent in menu_query.iter() {
         commands.entity(ent).despawn_recursive();
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn handle_start_button(
     mut commands: Commands,
-    mut interaction_query: Query<
-        (&Children, &mut ButtonActive, &Interaction),
-        Changed<Interaction>,
+    interaction_query: Query<
+        (&Children, &StartMenuAction, &Interaction),
+        (Changed<Interaction>, With<Button>),
     >,
+    mut active_query: Query<&mut ButtonActive>,
     mut image_query: Query<&mut UiImage>,
+    mut loaded_game: ResMut<LoadedGame>,
     ui_assets: Res<UiAssets>,
     ascii: Res<AsciiSheet>,
+    mut ev_text_popup: EventWriter<CreateTextPopupEvent>,
 ) {
-    for (children, mut active, interaction) in interaction_query.iter_mut() {
+    for (children, action, interaction) in interaction_query.iter() {
         let child = children.iter().next().unwrap();
         let mut image = image_query.get_mut(*child).unwrap();
 
         match interaction {
             Interaction::Clicked => {
-                if active.0 {
-                    image.0 = ui_assets.button_pressed.clone();
-                    create_fadeout(&mut commands, Some(GameState::Overworld), &ascii);
+                // Only the first click counts, the menu is on its way out after that
+                if !active_query.iter().all(|active| active.0) {
+                    continue;
+                }
+
+                loaded_game.0 = match action {
+                    StartMenuAction::Continue => match read_save() {
+                        Ok(save) => Some(save),
+                        Err(err) => {
+                            error!("Couldn't load the save: {:#}", err);
+                            // Only the outermost reason, the full chain is in the log
+                            ev_text_popup.send(CreateTextPopupEvent {
+                                text: format!("Couldn't load the save:\n{}", err),
+                                position: TextPopupPosition::Center,
+                                duration: 4.0,
+                                priority: PopupPriority::High,
+                                ..default()
+                            });
+                            continue;
+                        }
+                    },
+                    StartMenuAction::NewGame => None,
+                };
+
+                image.0 = ui_assets.button_pressed.clone();
+                create_fadeout(&mut commands, Some(GameState::Overworld), &ascii);
+                for mut active in active_query.iter_mut() {
                     active.0 = false;
                 }
             }
@@ -94,22 +131,56 @@ fn handle_start_button(
 }
 
 fn spawn_menu(mut commands: Commands, ui_assets: Res<UiAssets>) {
+    let mut buttons = Vec::new();
+    if save_exists() {
+        buttons.push(("Continue", StartMenuAction::Continue));
+    }
+    buttons.push(("New Game", StartMenuAction::NewGame));
+
     commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(StartMenu)
+        .insert(Name::new("Start Menu"))
+        .with_children(|parent| {
+            for (text, action) in buttons {
+                spawn_menu_button(parent, &ui_assets, text, action);
+            }
+        });
+}
+
+/// A button with its image as the first child, `action` is what the menu's handler matches on
+pub(crate) fn spawn_menu_button(
+    parent: &mut ChildBuilder,
+    ui_assets: &UiAssets,
+    text: &str,
+    action: impl Component,
+) {
+    parent
         .spawn_bundle(ButtonBundle {
             node: Default::default(),
             button: Default::default(),
             style: Style {
-                align_self: AlignSelf::Center,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::Center,
                 size: Size::new(Val::Percent(20.0), Val::Percent(10.0)),
-                margin: Rect::all(Val::Auto),
+                margin: Rect::all(Val::Percent(1.0)),
                 ..default()
             },
             color: Color::NONE.into(),
             ..default()
         })
         .insert(ButtonActive(true))
+        .insert(action)
         .with_children(|parent| {
             parent
                 .spawn_bundle(ImageBundle {
@@ -128,7 +199,7 @@ fn spawn_menu(mut commands: Commands, ui_assets: Res<UiAssets>) {
                     parent
                         .spawn_bundle(TextBundle {
                             text: Text::with_section(
-                                "Start Game",
+                                text,
                                 TextStyle {
                                     font: ui_assets.font_bold.clone(),
                                     font_size: 40.0,
@@ -0,0 +1,182 @@
+use crate::ascii::{spawn_ascii_sprite, AsciiSheet};
+use crate::equipment::StatBonus;
+use crate::game_ui::HealthBarBg;
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct StatusPlugin;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StatusKind {
+    /// Loses `potency` health at the start of every turn
+    Poison,
+    /// Skips turns
+    Stun,
+    DefenseUp,
+    AttackUp,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StatusEffect {
+    pub(crate) kind: StatusKind,
+    /// Turns of the afflicted combatant left until it wears off
+    pub(crate) turns: usize,
+    #[serde(default)]
+    pub(crate) potency: isize,
+    /// Stays on after the fight instead of wearing off with it
+    #[serde(default)]
+    pub(crate) persistent: bool,
+}
+
+/// A status effect an attack has a chance to leave on its target
+#[derive(Deserialize, Clone)]
+pub struct Infliction {
+    pub(crate) status: StatusEffect,
+    #[serde(default = "Infliction::always")]
+    pub(crate) chance: f32,
+}
+
+#[derive(Component, Serialize, Deserialize, Default, Clone)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+/// What happened to a combatant when their statuses ticked at the start of their turn
+#[derive(Default)]
+pub struct StatusTick {
+    pub(crate) poison_damage: isize,
+    pub(crate) stunned: bool,
+}
+
+#[derive(Component)]
+pub struct StatusIcon {
+    owner: Entity,
+}
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(update_status_icons).add_system_set(
+            SystemSet::on_exit(GameState::Combat).with_system(clear_combat_statuses),
+        );
+    }
+}
+
+impl StatusKind {
+    /// Glyph in the ascii sheet and its color
+    fn icon(&self) -> (usize, Color) {
+        match self {
+            StatusKind::Poison => (5, Color::rgb(0.4, 0.9, 0.3)),
+            StatusKind::Stun => (15, Color::YELLOW),
+            StatusKind::DefenseUp => (4, Color::rgb(0.4, 0.6, 1.0)),
+            StatusKind::AttackUp => (24, Color::rgb(1.0, 0.4, 0.3)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusKind::Poison => "poisoned",
+            StatusKind::Stun => "stunned",
+            StatusKind::DefenseUp => "guarded",
+            StatusKind::AttackUp => "fired up",
+        }
+    }
+}
+
+impl Infliction {
+    fn always() -> f32 {
+        1.0
+    }
+}
+
+impl StatusEffects {
+    /// Reapplying a status refreshes it rather than stacking it
+    pub fn add(&mut self, status: StatusEffect) {
+        self.effects.retain(|effect| effect.kind != status.kind);
+        self.effects.push(status);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn bonus(&self) -> StatBonus {
+        let mut bonus = StatBonus::default();
+        for effect in self.effects.iter() {
+            match effect.kind {
+                StatusKind::DefenseUp => bonus.defense += effect.potency,
+                StatusKind::AttackUp => bonus.attack += effect.potency,
+                StatusKind::Poison | StatusKind::Stun => {}
+            }
+        }
+        bonus
+    }
+
+    /// Applies the effects for one turn of the afflicted combatant and counts their turns down
+    pub fn tick(&mut self) -> StatusTick {
+        let mut tick = StatusTick::default();
+        for effect in self.effects.iter_mut() {
+            match effect.kind {
+                StatusKind::Poison => tick.poison_damage += effect.potency,
+                StatusKind::Stun => tick.stunned = true,
+                StatusKind::DefenseUp | StatusKind::AttackUp => {}
+            }
+            effect.turns = effect.turns.saturating_sub(1);
+        }
+        self.effects.retain(|effect| effect.turns > 0);
+        tick
+    }
+}
+
+fn clear_combat_statuses(mut status_query: Query<&mut StatusEffects>) {
+    for mut statuses in status_query.iter_mut() {
+        statuses.effects.retain(|effect| effect.persistent);
+    }
+}
+
+/// Redraws the icons to the right of the health bar whenever the owner's statuses change
+fn update_status_icons(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    owner_query: Query<(Entity, &StatusEffects, &Children), Changed<StatusEffects>>,
+    health_bar_bg_query: Query<Entity, With<HealthBarBg>>,
+    icon_query: Query<(Entity, &StatusIcon)>,
+) {
+    for (owner, statuses, children) in owner_query.iter() {
+        for (icon, status_icon) in icon_query.iter() {
+            if status_icon.owner == owner {
+                commands.entity(icon).despawn_recursive();
+            }
+        }
+
+        let health_bar_bg = match children
+            .iter()
+            .find(|&&child| health_bar_bg_query.get(child).is_ok())
+        {
+            Some(&health_bar_bg) => health_bar_bg,
+            None => continue,
+        };
+
+        for (i, effect) in statuses.effects.iter().enumerate() {
+            let (index, color) = effect.kind.icon();
+            let icon = spawn_ascii_sprite(
+                &mut commands,
+                &ascii,
+                index,
+                color,
+                // Right next to the health bar, which is 0.104 wide
+                Vec3::new(0.066 + i as f32 * 0.022, 0.0, 0.1),
+                Vec3::splat(0.22),
+            );
+            commands
+                .entity(icon)
+                .insert(StatusIcon { owner })
+                .insert(Name::new(format!("StatusIcon {:?}", effect.kind)));
+            commands.entity(health_bar_bg).add_child(icon);
+        }
+    }
+}
@@ -1,7 +1,18 @@
 use crate::ascii::{spawn_ascii_sprite, AsciiSheet};
+use crate::combat::{
+    CombatState, EncounterTable, EnemyDatabase, PendingEncounter, ENEMY_DATABASE_PATH,
+};
 use crate::npc::Npc;
+use crate::party::{PartyMember, SavedParty};
+use crate::player::Player;
+use crate::save::LoadedGame;
 use crate::{GameState, TILE_SIZE};
+use anyhow::{bail, Context};
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset};
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::{HashMap, HashSet};
+use serde::Deserialize;
 
 pub struct TileMapPlugin;
 
@@ -9,17 +20,98 @@ pub struct TileMapPlugin;
 pub struct Map;
 
 #[derive(Component)]
-pub struct EncounterSpawner;
+pub struct EncounterSpawner {
+    pub(crate) table: String,
+}
 
 #[derive(Component)]
 pub struct TileCollider;
 
+/// Fixed fight that blocks the way until it's won, ids are unique across maps
+#[derive(Component)]
+pub struct BossTile {
+    pub(crate) id: String,
+    pub(crate) table: String,
+}
+
+/// Ids of the boss tiles the player has beaten, they're passable from then on
+#[derive(Default, Clone)]
+pub struct DefeatedBosses(pub(crate) HashSet<String>);
+
+#[derive(Component, Deserialize, Clone)]
+pub struct WarpTile {
+    pub(crate) map: String,
+    pub(crate) spawn: String,
+}
+
+/// Map authored as a RON file in `assets/maps`, see `overworld.map` for the format
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "8b6597f7-e43b-414d-8a8e-f6b820e4248e"]
+pub struct MapDefinition {
+    legend: HashMap<char, TileDefinition>,
+    /// Named tile coordinates (column, line) where the player can be placed
+    #[serde(default)]
+    spawns: HashMap<String, (usize, usize)>,
+    /// Weighted enemy tables, referenced by name from encounter tiles
+    #[serde(default)]
+    encounters: HashMap<String, EncounterTable>,
+    layout: String,
+}
+
+/// What a glyph in the map layout turns into
+#[derive(Deserialize)]
+pub struct TileDefinition {
+    color: (f32, f32, f32),
+    #[serde(default)]
+    collider: bool,
+    #[serde(default)]
+    encounter: Option<String>,
+    #[serde(default)]
+    npc: Option<Npc>,
+    #[serde(default)]
+    warp: Option<WarpTile>,
+    /// Encounter table fought when walking into the tile
+    #[serde(default)]
+    boss: Option<String>,
+}
+
+/// The map the player is on and the spawn point to place them at once it's spawned,
+/// no spawn point keeps the player where they are
+pub struct CurrentMap {
+    pub(crate) name: String,
+    spawn: Option<String>,
+    handle: Handle<MapDefinition>,
+}
+
+pub struct ChangeMapEvent {
+    pub(crate) warp: WarpTile,
+}
+
+pub struct MapSpawnedEvent;
+
+#[derive(Default)]
+pub struct MapLoader;
+
 impl Plugin for TileMapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_resume(GameState::Overworld).with_system(show_map))
-            .add_system_set(SystemSet::on_pause(GameState::Overworld).with_system(hide_map))
+        app.add_asset::<MapDefinition>()
+            .init_asset_loader::<MapLoader>()
+            .add_event::<ChangeMapEvent>()
+            .add_event::<MapSpawnedEvent>()
+            .init_resource::<DefeatedBosses>()
+            .add_startup_system(load_starting_map)
+            .add_system_set(SystemSet::on_exit(GameState::Combat).with_system(show_map))
+            .add_system_set(SystemSet::on_enter(GameState::Combat).with_system(hide_map))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Overworld).with_system(load_starting_map),
+            )
             .add_system_set(
-                SystemSet::on_enter(GameState::Overworld).with_system(create_simple_map),
+                SystemSet::on_enter(CombatState::Reward).with_system(clear_defeated_boss),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(change_map)
+                    .with_system(spawn_map.after(change_map)),
             );
     }
 }
@@ -50,64 +142,113 @@ fn show_map(
     }
 }
 
-fn create_simple_map(mut commands: Commands, ascii: Res<AsciiSheet>) {
-    const MAP_STR: &str = r"####################
-#....~~~~~~........#
-#....~~~~~~........#
-#....######~~~~~~~~#
-#....#..@.#~~~~~~~~#
-#.........#~~~~~~~~#
-###########........#
-          #.@......#
-          #........#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #~~~~~~~~#
-          #........#
-          #...@....#
-          ##########";
+fn change_map(
+    mut commands: Commands,
+    mut ev_change_map: EventReader<ChangeMapEvent>,
+    mut current_map: ResMut<CurrentMap>,
+    assets: Res<AssetServer>,
+    map_query: Query<Entity, With<Map>>,
+) {
+    for event in ev_change_map.iter() {
+        for map in map_query.iter() {
+            commands.entity(map).despawn_recursive();
+        }
+
+        *current_map = CurrentMap::new(&assets, &event.warp.map, Some(&event.warp.spawn));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_map(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapDefinition>>,
+    assets: Res<AssetServer>,
+    defeated_bosses: Res<DefeatedBosses>,
+    member_query: Query<&PartyMember>,
+    saved_party: Option<Res<SavedParty>>,
+    map_query: Query<(), With<Map>>,
+    mut player_query: Query<(&mut Player, &mut Transform)>,
+    mut ev_map_spawned: EventWriter<MapSpawnedEvent>,
+    mut reported_failure: Local<Option<String>>,
+) {
+    // Wait for the old map to be gone and for the player and saved party to exist, recruited NPCs
+    // would be back on the map otherwise
+    if !map_query.is_empty() || player_query.is_empty() || saved_party.is_some() {
+        return;
+    }
+
+    let map = match maps.get(&current_map.handle) {
+        Some(map) => map,
+        None => {
+            // The loader already logged the reason, just make it obvious why the screen is empty
+            if assets.get_load_state(&current_map.handle) == LoadState::Failed
+                && reported_failure.as_ref() != Some(&current_map.name)
+            {
+                error!(
+                    "Map '{}' failed to load, nothing to spawn",
+                    current_map.name
+                );
+                *reported_failure = Some(current_map.name.clone());
+            }
+            return;
+        }
+    };
 
     let mut tiles = Vec::new();
 
-    for (y, line) in MAP_STR.lines().enumerate() {
+    for (y, line) in map.layout.lines().enumerate() {
         for (x, char) in line.chars().enumerate() {
-            let color = match char {
-                '#' => Color::rgb(0.7, 0.7, 0.7),
-                '@' => Color::rgb(0.5, 0.5, 0.2),
-                '~' => Color::rgb(0.2, 0.9, 0.2),
-                _ => Color::rgb(0.9, 0.9, 0.9),
+            // Every glyph is checked against the legend by the loader
+            let tile_def = &map.legend[&char];
+            let (r, g, b) = tile_def.color;
+
+            let boss = tile_def
+                .boss
+                .as_ref()
+                .map(|table| (current_map.boss_id(table), table))
+                .filter(|(id, _)| !defeated_bosses.0.contains(id));
+            let recruited = match &tile_def.npc {
+                Some(Npc::Recruit(id)) => member_query.iter().any(|member| &member.id == id),
+                _ => false,
             };
+            // Beaten bosses and recruited NPCs leave an empty floor tile behind
+            let gone = recruited || (tile_def.boss.is_some() && boss.is_none());
+            let glyph = if gone { ' ' } else { char };
 
             let tile = spawn_ascii_sprite(
                 &mut commands,
                 &ascii,
-                char as usize,
-                color,
+                glyph as usize,
+                Color::rgb(r, g, b),
                 Vec3::new(x as f32 * TILE_SIZE, -(y as f32) * TILE_SIZE, 100.0),
                 Vec3::splat(1.0),
             );
 
-            if char == '#' {
+            if tile_def.collider && !gone {
                 commands.entity(tile).insert(TileCollider);
             }
 
-            if char == '~' {
-                commands.entity(tile).insert(EncounterSpawner);
+            if let Some(table) = &tile_def.encounter {
+                commands.entity(tile).insert(EncounterSpawner {
+                    table: table.clone(),
+                });
+            }
+
+            if let (Some(npc), false) = (&tile_def.npc, gone) {
+                commands.entity(tile).insert(npc.clone());
             }
 
-            if char == '@' {
-                commands
-                    .entity(tile)
-                    .insert(Npc::Healer)
-                    .insert(TileCollider);
+            if let Some(warp) = &tile_def.warp {
+                commands.entity(tile).insert(warp.clone());
+            }
+
+            if let Some((id, table)) = boss {
+                commands.entity(tile).insert(TileCollider).insert(BossTile {
+                    id,
+                    table: table.clone(),
+                });
             }
 
             tiles.push(tile);
@@ -117,8 +258,238 @@ fn create_simple_map(mut commands: Commands, ascii: Res<AsciiSheet>) {
     commands
         .spawn()
         .insert(Map)
-        .insert(Name::new("Map"))
+        .insert(Name::new(format!("Map - {}", current_map.name)))
         .insert(Transform::default())
         .insert(GlobalTransform::default())
         .push_children(&tiles);
+
+    let (mut player, mut transform) = player_query.single_mut();
+    if let Some(spawn) = &current_map.spawn {
+        match map.spawns.get(spawn) {
+            Some(&(x, y)) => {
+                transform.translation.x = x as f32 * TILE_SIZE;
+                transform.translation.y = -(y as f32) * TILE_SIZE;
+            }
+            None => error!("Map '{}' has no spawn point '{}'", current_map.name, spawn),
+        }
+    }
+    player.active = true;
+
+    ev_map_spawned.send(MapSpawnedEvent);
+}
+
+fn load_starting_map(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    loaded_game: Option<Res<LoadedGame>>,
+) {
+    // The player is already placed at the saved position when continuing
+    let (current_map, defeated_bosses) = match loaded_game.as_ref().and_then(|game| game.0.as_ref())
+    {
+        Some(save) => (
+            CurrentMap::new(&assets, &save.map, None),
+            DefeatedBosses(save.defeated_bosses.iter().cloned().collect()),
+        ),
+        None => (
+            CurrentMap::new(&assets, "overworld", Some("start")),
+            DefeatedBosses::default(),
+        ),
+    };
+    commands.insert_resource(current_map);
+    commands.insert_resource(defeated_bosses);
+}
+
+/// Opens up the tile of the boss that was just beaten, the map is still spawned under the fight
+fn clear_defeated_boss(
+    mut commands: Commands,
+    encounter: Res<PendingEncounter>,
+    mut defeated_bosses: ResMut<DefeatedBosses>,
+    mut boss_query: Query<(Entity, &BossTile, &mut TextureAtlasSprite)>,
+) {
+    // A boss that ran off is still out there
+    let id = match &encounter.boss {
+        Some(id) if !encounter.enemy_fled => id,
+        _ => return,
+    };
+
+    defeated_bosses.0.insert(id.clone());
+    for (tile, boss, mut sprite) in boss_query.iter_mut() {
+        if &boss.id == id {
+            sprite.index = ' ' as usize;
+            commands
+                .entity(tile)
+                .remove::<BossTile>()
+                .remove::<TileCollider>();
+        }
+    }
+}
+
+impl CurrentMap {
+    fn new(assets: &AssetServer, name: &str, spawn: Option<&str>) -> Self {
+        CurrentMap {
+            name: name.to_string(),
+            spawn: spawn.map(str::to_string),
+            handle: assets.load(&map_path(name)),
+        }
+    }
+
+    fn boss_id(&self, table: &str) -> String {
+        format!("{}/{}", self.name, table)
+    }
+
+    pub fn encounter_table<'a>(
+        &self,
+        maps: &'a Assets<MapDefinition>,
+        table: &str,
+    ) -> Option<&'a EncounterTable> {
+        maps.get(&self.handle)?.encounters.get(table)
+    }
+}
+
+impl MapDefinition {
+    fn validate(&self, enemies: &EnemyDatabase) -> Result<(), anyhow::Error> {
+        for (y, line) in self.layout.lines().enumerate() {
+            for (x, char) in line.chars().enumerate() {
+                if !self.legend.contains_key(&char) {
+                    bail!(
+                        "unknown glyph '{}' at line {}, column {}",
+                        char,
+                        y + 1,
+                        x + 1
+                    );
+                }
+            }
+        }
+
+        if let Some(glyph) = self.legend.keys().find(|&&glyph| glyph as usize > 255) {
+            bail!("glyph '{}' is not in the ascii sheet", glyph);
+        }
+
+        for (glyph, tile_def) in self.legend.iter() {
+            for table in tile_def.encounter.iter().chain(tile_def.boss.iter()) {
+                if !self.encounters.contains_key(table) {
+                    bail!("glyph '{}' uses unknown encounter table '{}'", glyph, table);
+                }
+            }
+        }
+
+        for (name, table) in self.encounters.iter() {
+            table
+                .validate(enemies)
+                .with_context(|| format!("in encounter table '{}'", name))?;
+        }
+
+        for (name, &(x, y)) in self.spawns.iter() {
+            let in_bounds = self
+                .layout
+                .lines()
+                .nth(y)
+                .is_some_and(|line| x < line.chars().count());
+            if !in_bounds {
+                bail!(
+                    "spawn point '{}' at ({}, {}) is outside the map",
+                    name,
+                    x,
+                    y
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn warps(&self) -> impl Iterator<Item = (&char, &WarpTile)> {
+        self.legend
+            .iter()
+            .filter_map(|(glyph, tile_def)| Some((glyph, tile_def.warp.as_ref()?)))
+    }
+}
+
+impl WarpTile {
+    /// Checks the warp against the map it leads to
+    fn validate(&self, target: &MapDefinition) -> Result<(), anyhow::Error> {
+        if !target.spawns.contains_key(&self.spawn) {
+            bail!("map '{}' has no spawn point '{}'", self.map, self.spawn);
+        }
+        Ok(())
+    }
+}
+
+pub fn map_path(name: &str) -> String {
+    format!("maps/{}.map", name)
+}
+
+impl AssetLoader for MapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let map: MapDefinition = ron::de::from_bytes(bytes)?;
+            // Encounter tables name enemies, so the database is read here as well to check them
+            let enemy_bytes = load_context.read_asset_bytes(ENEMY_DATABASE_PATH).await?;
+            let enemies: EnemyDatabase = ron::de::from_bytes(&enemy_bytes)
+                .with_context(|| format!("couldn't read {}", ENEMY_DATABASE_PATH))?;
+            let path = load_context.path().display().to_string();
+            map.validate(&enemies)
+                .with_context(|| format!("invalid map {}", path))?;
+            // The maps warps lead to are read as well, a missing spawn point fails here and not
+            // once the player steps on the warp
+            for (glyph, warp) in map.warps() {
+                let target_bytes = load_context
+                    .read_asset_bytes(map_path(&warp.map))
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "invalid map {}: glyph '{}' warps to '{}'",
+                            path, glyph, warp.map
+                        )
+                    })?;
+                let target: MapDefinition = ron::de::from_bytes(&target_bytes)
+                    .with_context(|| format!("couldn't read map '{}'", warp.map))?;
+                warp.validate(&target)
+                    .with_context(|| format!("invalid map {}: glyph '{}'", path, glyph))?;
+            }
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_maps_are_valid() {
+        let enemies: EnemyDatabase =
+            ron::from_str(include_str!("../assets/data/enemies.ron")).unwrap();
+        let mut maps = HashMap::default();
+        for entry in std::fs::read_dir("assets/maps").unwrap() {
+            let path = entry.unwrap().path();
+            let map: MapDefinition = ron::from_str(&std::fs::read_to_string(&path).unwrap())
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            if let Err(err) = map.validate(&enemies) {
+                panic!("{}: {:#}", path.display(), err);
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            maps.insert(name, map);
+        }
+
+        for (name, map) in maps.iter() {
+            for (glyph, warp) in map.warps() {
+                let target = maps.get(&warp.map).unwrap_or_else(|| {
+                    panic!("{}: glyph '{}' warps to '{}'", name, glyph, warp.map)
+                });
+                if let Err(err) = warp.validate(target) {
+                    panic!("{}: {:#}", name, err);
+                }
+            }
+        }
+    }
 }